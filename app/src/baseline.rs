@@ -0,0 +1,149 @@
+use std::{collections::BTreeMap, io, path::Path};
+
+use data_transfer::baseline::{Baseline, BaselineCapture};
+use data_transfer::conversions::MagneticField;
+use serde::{Deserialize, Serialize};
+
+use crate::calibration::{load_json, save_json};
+
+/// The baseline of every sensor of a board, keyed by I2C address.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BoardBaseline {
+    pub sensors: BTreeMap<u8, Baseline>,
+}
+
+impl BoardBaseline {
+    /// Loads the baseline at `path`, or no baseline if there is no such file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        load_json(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save_json(self, path)
+    }
+
+    pub fn apply(&self, sensor: u8, field: MagneticField) -> MagneticField {
+        match self.sensors.get(&sensor) {
+            Some(baseline) => baseline.subtract(field),
+            None => field,
+        }
+    }
+}
+
+/// Averages a number of readings of every sensor in the layout, and of any other
+/// sensor that sends some.
+pub struct BoardCapture {
+    readings: u32,
+    sensors: BTreeMap<u8, BaselineCapture>,
+}
+
+impl BoardCapture {
+    /// Waits for `readings` readings of each of the `expected` sensors.
+    pub fn new(readings: u32, expected: impl IntoIterator<Item = u8>) -> Self {
+        Self {
+            readings,
+            sensors: expected
+                .into_iter()
+                .map(|sensor| (sensor, BaselineCapture::default()))
+                .collect(),
+        }
+    }
+
+    pub fn add(&mut self, sensor: u8, field: &MagneticField) {
+        self.sensors.entry(sensor).or_default().add(field);
+    }
+
+    /// The sensors that have not sent enough readings yet.
+    pub fn waiting(&self) -> impl Iterator<Item = u8> + '_ {
+        self.sensors
+            .iter()
+            .filter(|(_, capture)| capture.readings() < self.readings)
+            .map(|(sensor, _)| *sensor)
+    }
+
+    /// Whether every expected sensor, and every other sensor seen, has sent enough
+    /// readings.
+    pub fn is_done(&self) -> bool {
+        !self.sensors.is_empty() && self.waiting().next().is_none()
+    }
+
+    pub fn baseline(&self) -> BoardBaseline {
+        BoardBaseline {
+            sensors: self
+                .sensors
+                .iter()
+                .map(|(sensor, capture)| (*sensor, capture.baseline()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_transfer::conversions::MagneticValue;
+
+    fn field(x: f64, z: Option<f64>) -> MagneticField {
+        MagneticField {
+            x: Some(MagneticValue::uT(x)),
+            y: None,
+            z: z.map(MagneticValue::uT),
+            t: None,
+        }
+    }
+
+    #[test]
+    fn capture_waits_for_every_expected_sensor() {
+        let mut capture = BoardCapture::new(2, [0x0C, 0x0D]);
+        assert!(!capture.is_done());
+        capture.add(0x0C, &field(1.0, None));
+        capture.add(0x0C, &field(3.0, None));
+        assert!(!capture.is_done());
+        assert_eq!(capture.waiting().collect::<Vec<_>>(), [0x0D]);
+
+        capture.add(0x0D, &field(5.0, Some(1.0)));
+        capture.add(0x0D, &field(5.0, Some(2.0)));
+        assert!(capture.is_done());
+        let baseline = capture.baseline();
+        assert_eq!(
+            baseline.sensors.keys().copied().collect::<Vec<_>>(),
+            [0x0C, 0x0D]
+        );
+    }
+
+    #[test]
+    fn capture_waits_for_sensors_outside_the_layout() {
+        let mut capture = BoardCapture::new(1, [0x0C]);
+        capture.add(0x0C, &field(1.0, None));
+        assert!(capture.is_done());
+        capture.add(0x20, &field(1.0, None));
+        assert!(capture.is_done());
+
+        let mut capture = BoardCapture::new(2, [0x0C]);
+        capture.add(0x20, &field(1.0, None));
+        capture.add(0x0C, &field(1.0, None));
+        capture.add(0x0C, &field(1.0, None));
+        assert_eq!(capture.waiting().collect::<Vec<_>>(), [0x20]);
+    }
+
+    #[test]
+    fn nothing_to_capture_is_never_done() {
+        assert!(!BoardCapture::new(1, []).is_done());
+    }
+
+    #[test]
+    fn baseline_is_subtracted_from_its_sensor_only() {
+        let mut capture = BoardCapture::new(2, [0x0C]);
+        capture.add(0x0C, &field(1.0, Some(10.0)));
+        capture.add(0x0C, &field(3.0, None));
+        let baseline = capture.baseline();
+
+        let corrected = baseline.apply(0x0C, field(5.0, Some(15.0))).vector();
+        assert_eq!(corrected.x, Some(3.0));
+        assert_eq!(corrected.z, Some(5.0));
+        assert_eq!(corrected.y, None);
+
+        let unchanged = baseline.apply(0x0D, field(5.0, Some(15.0))).vector();
+        assert_eq!((unchanged.x, unchanged.z), (Some(5.0), Some(15.0)));
+    }
+}
@@ -0,0 +1,393 @@
+use std::{collections::BTreeMap, fmt, fs, io, path::Path};
+
+use data_transfer::conversions::{Calibration, MagneticField};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::client::Frames;
+use crate::layout::Layout;
+use crate::readings;
+
+/// The calibration of every sensor of a board, keyed by I2C address.
+///
+/// Sensors without a calibration are left uncorrected.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BoardCalibration {
+    pub sensors: BTreeMap<u8, Calibration>,
+}
+
+impl BoardCalibration {
+    /// Loads the calibration at `path`, or no calibration if there is no such file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        load_json(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save_json(self, path)
+    }
+
+    pub fn apply(&self, sensor: u8, field: MagneticField) -> MagneticField {
+        match self.sensors.get(&sensor) {
+            Some(calibration) => field.calibrate(calibration),
+            None => field,
+        }
+    }
+}
+
+/// Reads `path` as JSON, or the default if there is no such file.
+pub fn load_json<T: DeserializeOwned + Default>(path: &Path) -> io::Result<T> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn save_json<T: Serialize>(value: &T, path: &Path) -> io::Result<()> {
+    let json = serde_json::to_vec_pretty(value)?;
+    fs::write(path, json)
+}
+
+/// One unknown per coefficient of the quadric fitted to the samples.
+pub const MIN_SAMPLES: usize = 9;
+
+#[derive(Debug, PartialEq)]
+pub enum FitError {
+    TooFewSamples(usize),
+    /// The samples do not pin down an ellipsoid, typically because the sensor
+    /// was only turned around one axis.
+    Degenerate,
+    /// The best fitting quadric is not an ellipsoid.
+    NotAnEllipsoid,
+}
+
+impl fmt::Display for FitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FitError::TooFewSamples(samples) => write!(
+                f,
+                "{} samples, at least {} are needed",
+                samples, MIN_SAMPLES
+            ),
+            FitError::Degenerate => write!(f, "the sensor was not turned in enough directions"),
+            FitError::NotAnEllipsoid => write!(f, "the samples do not lie on an ellipsoid"),
+        }
+    }
+}
+
+impl std::error::Error for FitError {}
+
+/// Fits an ellipsoid to samples of a constant field seen from many orientations,
+/// and returns the calibration that maps it back onto a sphere.
+///
+/// The sphere has a radius of `field_ut` when given, otherwise the volume of the
+/// ellipsoid is kept, so the calibration does not change the overall scale.
+pub fn fit(samples: &[[f64; 3]], field_ut: Option<f64>) -> Result<Calibration, FitError> {
+    if samples.len() < MIN_SAMPLES {
+        return Err(FitError::TooFewSamples(samples.len()));
+    }
+
+    // Fitting around the mean and at unit scale keeps the normal equations well conditioned.
+    let count = samples.len() as f64;
+    let mean = [0, 1, 2].map(|axis| samples.iter().map(|s| s[axis]).sum::<f64>() / count);
+    let scale = samples
+        .iter()
+        .flat_map(|sample| (0..3).map(move |axis| (sample[axis] - mean[axis]).abs()))
+        .fold(0.0, f64::max);
+    if scale == 0.0 {
+        return Err(FitError::Degenerate);
+    }
+
+    // Least squares fit of a x² + b y² + c z² + 2d xy + 2e xz + 2f yz + 2g x + 2h y + 2i z = 1.
+    let mut normal = [[0.0; 9]; 9];
+    let mut rhs = [0.0; 9];
+    for sample in samples {
+        let [x, y, z] = [0, 1, 2].map(|axis| (sample[axis] - mean[axis]) / scale);
+        let row = [
+            x * x,
+            y * y,
+            z * z,
+            2.0 * x * y,
+            2.0 * x * z,
+            2.0 * y * z,
+            2.0 * x,
+            2.0 * y,
+            2.0 * z,
+        ];
+        for i in 0..9 {
+            for j in 0..9 {
+                normal[i][j] += row[i] * row[j];
+            }
+            rhs[i] += row[i];
+        }
+    }
+    let [a, b, c, d, e, f, g, h, i] = solve(normal, rhs).ok_or(FitError::Degenerate)?;
+
+    // (p − center)ᵀ · shape · (p − center) = 1
+    let quadric = [[a, d, e], [d, b, f], [e, f, c]];
+    let center = solve(quadric, [-g, -h, -i]).ok_or(FitError::NotAnEllipsoid)?;
+    let level = 1.0 + dot(center, mat_vec(quadric, center));
+    if level <= 0.0 {
+        return Err(FitError::NotAnEllipsoid);
+    }
+    let shape = quadric.map(|row| row.map(|value| value / level));
+
+    let (eigenvalues, eigenvectors) = symmetric_eigen(shape);
+    if eigenvalues.iter().any(|value| *value <= 0.0) {
+        return Err(FitError::NotAnEllipsoid);
+    }
+    // The ellipsoid has radii 1/√λ in units of `scale`.
+    let radius = match field_ut {
+        Some(field_ut) => field_ut / scale,
+        None => eigenvalues.iter().product::<f64>().powf(-1.0 / 6.0),
+    };
+
+    // matrix = radius · shape^½, rescaled back to µT.
+    let mut matrix = [[0.0; 3]; 3];
+    for (row, values) in matrix.iter_mut().enumerate() {
+        for (column, value) in values.iter_mut().enumerate() {
+            *value = (0..3)
+                .map(|k| eigenvectors[row][k] * eigenvalues[k].sqrt() * eigenvectors[column][k])
+                .sum::<f64>()
+                * radius;
+        }
+    }
+    Ok(Calibration {
+        offset: [0, 1, 2].map(|axis| mean[axis] + center[axis] * scale),
+        matrix,
+    })
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn mat_vec(matrix: [[f64; 3]; 3], vector: [f64; 3]) -> [f64; 3] {
+    matrix.map(|row| dot(row, vector))
+}
+
+/// Solves `matrix · x = rhs` by Gaussian elimination, or `None` if the matrix is singular.
+fn solve<const N: usize>(mut matrix: [[f64; N]; N], mut rhs: [f64; N]) -> Option<[f64; N]> {
+    let largest = matrix
+        .iter()
+        .flatten()
+        .fold(0.0, |max: f64, v| max.max(v.abs()));
+    for column in 0..N {
+        let pivot = (column..N).max_by(|a, b| {
+            matrix[*a][column]
+                .abs()
+                .total_cmp(&matrix[*b][column].abs())
+        })?;
+        if matrix[pivot][column].abs() <= largest * 1e-12 {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+        let pivot_row = matrix[column];
+        for row in column + 1..N {
+            let factor = matrix[row][column] / pivot_row[column];
+            for (value, pivot) in matrix[row][column..].iter_mut().zip(&pivot_row[column..]) {
+                *value -= factor * pivot;
+            }
+            rhs[row] -= factor * rhs[column];
+        }
+    }
+    let mut solution = [0.0; N];
+    for row in (0..N).rev() {
+        let known: f64 = (row + 1..N).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (rhs[row] - known) / matrix[row][row];
+    }
+    Some(solution)
+}
+
+/// Eigenvalues and eigenvectors, as columns, of a symmetric matrix by Jacobi rotations.
+fn symmetric_eigen(mut matrix: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut vectors = Calibration::IDENTITY.matrix;
+    for _ in 0..50 {
+        let (p, q) = [(0, 1), (0, 2), (1, 2)]
+            .into_iter()
+            .max_by(|a, b| matrix[a.0][a.1].abs().total_cmp(&matrix[b.0][b.1].abs()))
+            .unwrap();
+        if matrix[p][q].abs() < 1e-15 {
+            break;
+        }
+        let theta = 0.5 * (2.0 * matrix[p][q]).atan2(matrix[q][q] - matrix[p][p]);
+        let (sin, cos) = theta.sin_cos();
+        // matrix ← Jᵀ · matrix · J and vectors ← vectors · J
+        for row in matrix.iter_mut().chain(vectors.iter_mut()) {
+            let (kp, kq) = (row[p], row[q]);
+            row[p] = cos * kp - sin * kq;
+            row[q] = sin * kp + cos * kq;
+        }
+        let (row_p, row_q) = (matrix[p], matrix[q]);
+        matrix[p] = [0, 1, 2].map(|k| cos * row_p[k] - sin * row_q[k]);
+        matrix[q] = [0, 1, 2].map(|k| sin * row_p[k] + cos * row_q[k]);
+    }
+    ([matrix[0][0], matrix[1][1], matrix[2][2]], vectors)
+}
+
+/// Collects readings until the frames end or Ctrl-C is pressed, fits a calibration
+/// to every sensor and saves them to `output`.
+pub async fn calibrate(
+    frames: &mut Frames,
+    layout: &Layout,
+    field_ut: Option<f64>,
+    output: &Path,
+) -> io::Result<()> {
+    let mut samples: BTreeMap<u8, Vec<[f64; 3]>> = BTreeMap::new();
+    eprintln!("Collecting readings, turn the board in every direction and press Ctrl-C when done");
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        let envelope = tokio::select! {
+            envelope = frames.recv() => envelope,
+            _ = &mut ctrl_c => None,
+        };
+        let Some(envelope) = envelope else {
+            break;
+        };
+        match readings(&envelope, layout) {
+            Ok(readings) => {
+                for (sensor, field) in readings {
+                    if let Some(sample) = field.vector().microtesla() {
+                        samples.entry(sensor).or_default().push(sample);
+                    }
+                }
+            }
+            Err(err) => eprintln!(
+                "Left out the readings of frame {}: {}",
+                envelope.sequence, err
+            ),
+        }
+    }
+
+    let mut calibration = BoardCalibration::default();
+    for (sensor, samples) in samples {
+        match fit(&samples, field_ut) {
+            Ok(fitted) => {
+                eprintln!(
+                    "Sensor {:#04x}: {} samples, offset {:.2?} µT",
+                    sensor,
+                    samples.len(),
+                    fitted.offset
+                );
+                calibration.sensors.insert(sensor, fitted);
+            }
+            Err(err) => eprintln!("Sensor {:#04x}: not calibrated, {}", sensor, err),
+        }
+    }
+    calibration.save(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_transfer::conversions::FieldVector;
+
+    /// Points of a field of `radius` seen from evenly spread directions.
+    fn sphere(radius: f64) -> Vec<[f64; 3]> {
+        let mut points = Vec::new();
+        for i in 0..12 {
+            for j in 0..24 {
+                let polar = std::f64::consts::PI * (i as f64 + 0.5) / 12.0;
+                let azimuth = std::f64::consts::PI * 2.0 * j as f64 / 24.0;
+                points.push([
+                    radius * polar.sin() * azimuth.cos(),
+                    radius * polar.sin() * azimuth.sin(),
+                    radius * polar.cos(),
+                ]);
+            }
+        }
+        points
+    }
+
+    /// Distorts the true field the way a sensor would: `distortion · field + offset`.
+    fn distort(points: &[[f64; 3]], distortion: [[f64; 3]; 3], offset: [f64; 3]) -> Vec<[f64; 3]> {
+        points
+            .iter()
+            .map(|point| {
+                let distorted = mat_vec(distortion, *point);
+                [0, 1, 2].map(|axis| distorted[axis] + offset[axis])
+            })
+            .collect()
+    }
+
+    fn corrected_norms(calibration: &Calibration, samples: &[[f64; 3]]) -> Vec<f64> {
+        samples
+            .iter()
+            .map(|[x, y, z]| {
+                calibration
+                    .apply(FieldVector::new(*x, *y, *z))
+                    .norm()
+                    .unwrap()
+                    .value()
+            })
+            .collect()
+    }
+
+    const DISTORTION: [[f64; 3]; 3] = [[1.2, 0.05, 0.0], [0.05, 0.9, 0.1], [0.0, 0.1, 1.05]];
+    const OFFSET: [f64; 3] = [35.0, -12.0, 80.0];
+
+    #[test]
+    fn maps_the_ellipsoid_onto_the_earth_field() {
+        let samples = distort(&sphere(50.0), DISTORTION, OFFSET);
+        let calibration = fit(&samples, Some(50.0)).unwrap();
+
+        for (fitted, expected) in calibration.offset.iter().zip(OFFSET) {
+            assert!(
+                (fitted - expected).abs() < 1e-6,
+                "{} != {}",
+                fitted,
+                expected
+            );
+        }
+        for norm in corrected_norms(&calibration, &samples) {
+            assert!((norm - 50.0).abs() < 1e-6, "{} != 50", norm);
+        }
+    }
+
+    #[test]
+    fn keeps_the_volume_without_a_field_strength() {
+        let samples = distort(&sphere(50.0), DISTORTION, OFFSET);
+        let calibration = fit(&samples, None).unwrap();
+
+        let det = |m: [[f64; 3]; 3]| {
+            m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+                - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+        };
+        let radius = 50.0 * det(DISTORTION).cbrt();
+        for norm in corrected_norms(&calibration, &samples) {
+            assert!((norm - radius).abs() < 1e-6, "{} != {}", norm, radius);
+        }
+    }
+
+    #[test]
+    fn rejects_samples_that_do_not_cover_every_direction() {
+        let flat: Vec<_> = sphere(50.0)
+            .into_iter()
+            .map(|[x, y, _]| [x, y, 0.0])
+            .collect();
+        assert_eq!(fit(&flat, None), Err(FitError::Degenerate));
+        assert_eq!(fit(&flat[..4], None), Err(FitError::TooFewSamples(4)));
+    }
+
+    #[test]
+    fn saves_and_loads_the_board_calibration() {
+        let path = std::env::temp_dir().join(format!("calibration-{}.json", std::process::id()));
+        let mut calibration = BoardCalibration::default();
+        calibration.sensors.insert(
+            0x0C,
+            Calibration {
+                offset: OFFSET,
+                matrix: DISTORTION,
+            },
+        );
+        calibration.save(&path).unwrap();
+        let loaded = BoardCalibration::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.sensors, calibration.sensors);
+
+        assert!(BoardCalibration::load(&path).unwrap().sensors.is_empty());
+    }
+}
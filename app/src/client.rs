@@ -0,0 +1,390 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use data_transfer::messaging::{
+    encode_frame, Ack, Command, CommandError, Envelope, FrameDecoder, Payload, Response,
+    MAX_FRAME_SIZE,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::{mpsc, oneshot, Mutex as AsyncMutex},
+    time::{timeout_at, Instant},
+};
+
+/// Requests waiting for their response, by sequence number.
+#[derive(Default)]
+struct Requests {
+    waiting: HashMap<u32, oneshot::Sender<Response>>,
+    /// Set once the reader has stopped, so no response can arrive anymore.
+    closed: bool,
+}
+
+type Pending = Arc<Mutex<Requests>>;
+
+/// Every frame from the board that is not a response to a command.
+///
+/// Unbounded so a slow consumer never drops frames nor stalls the responses.
+pub type Frames = mpsc::UnboundedReceiver<Envelope<Payload>>;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ClientConfig {
+    /// How long to wait for a response before sending the command again.
+    pub attempt_timeout: Duration,
+    /// How long to keep retrying before giving up on a command.
+    pub deadline: Duration,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            attempt_timeout: Duration::from_millis(500),
+            deadline: Duration::from_secs(3),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// No response arrived before the deadline.
+    Timeout,
+    /// The connection to the board was closed.
+    Closed,
+    /// The board answered with an error.
+    Command(CommandError),
+    /// The board answered with the wrong kind of acknowledgement.
+    Unexpected(Ack),
+    Encode(data_transfer::messaging::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Timeout => write!(f, "no response before the deadline"),
+            ClientError::Closed => write!(f, "connection closed"),
+            ClientError::Command(err) => write!(f, "board refused the command: {:?}", err),
+            ClientError::Unexpected(ack) => write!(f, "unexpected response: {:?}", ack),
+            ClientError::Encode(err) => write!(f, "failed to encode the command: {:?}", err),
+            ClientError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// Sends commands to the board and matches them with their responses.
+///
+/// Each command carries a sequence number that the board echoes in its response.
+/// Every other frame is forwarded to [`Frames`], so streaming keeps flowing
+/// while commands are in flight.
+pub struct Client<W> {
+    writer: AsyncMutex<W>,
+    pending: Pending,
+    sequence: AtomicU32,
+    config: ClientConfig,
+}
+
+impl<W: AsyncWrite + Unpin> Client<W> {
+    /// Spawns a task decoding frames from `reader` on the current runtime.
+    ///
+    /// The frames end once `reader` is closed.
+    pub fn new<R>(reader: R, writer: W, config: ClientConfig) -> (Self, Frames)
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let pending = Pending::default();
+        let (sender, frames) = mpsc::unbounded_channel();
+        tokio::spawn(read_frames(reader, pending.clone(), sender));
+        let client = Self {
+            writer: AsyncMutex::new(writer),
+            pending,
+            sequence: AtomicU32::new(0),
+            config,
+        };
+        (client, frames)
+    }
+
+    /// Sends `command` to `sensor` (0 for every sensor) and waits for its response.
+    ///
+    /// The command is sent again whenever an attempt times out, so it must be safe
+    /// for the board to apply it more than once.
+    pub async fn request(&self, sensor: u8, command: Command) -> Result<Ack, ClientError> {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let (sender, mut receiver) = oneshot::channel();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.closed {
+                return Err(ClientError::Closed);
+            }
+            pending.waiting.insert(sequence, sender);
+        }
+
+        let mut buffer = [0; MAX_FRAME_SIZE];
+        let envelope = Envelope::new(sensor, sequence, 0, command);
+        let frame = match encode_frame(&envelope, &mut buffer) {
+            Ok(frame) => frame,
+            Err(err) => {
+                self.pending.lock().unwrap().waiting.remove(&sequence);
+                return Err(ClientError::Encode(err));
+            }
+        };
+
+        let deadline = Instant::now() + self.config.deadline;
+        let result = loop {
+            if let Err(err) = self.send(frame).await {
+                break Err(err);
+            }
+            let attempt_end = deadline.min(Instant::now() + self.config.attempt_timeout);
+            match timeout_at(attempt_end, &mut receiver).await {
+                Ok(Ok(response)) => break response.map_err(ClientError::Command),
+                Ok(Err(_)) => break Err(ClientError::Closed),
+                Err(_) if attempt_end >= deadline => break Err(ClientError::Timeout),
+                Err(_) => continue,
+            }
+        };
+        self.pending.lock().unwrap().waiting.remove(&sequence);
+        result
+    }
+
+    pub async fn read_register(&self, sensor: u8, address: u8) -> Result<[u8; 2], ClientError> {
+        match self
+            .request(sensor, Command::ReadRegister { address })
+            .await?
+        {
+            Ack::Register { data, .. } => Ok(data),
+            ack => Err(ClientError::Unexpected(ack)),
+        }
+    }
+
+    /// The layout hash of the firmware.
+    pub async fn handshake(&self) -> Result<u32, ClientError> {
+        match self.request(0, Command::Handshake).await? {
+            Ack::Handshake { layout_hash } => Ok(layout_hash),
+            ack => Err(ClientError::Unexpected(ack)),
+        }
+    }
+
+    async fn send(&self, frame: &[u8]) -> Result<(), ClientError> {
+        let mut writer = self.writer.lock().await;
+        writer.write_all(frame).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+async fn read_frames<R: AsyncRead + Unpin>(
+    mut reader: R,
+    pending: Pending,
+    frames: mpsc::UnboundedSender<Envelope<Payload>>,
+) {
+    let mut decoder = FrameDecoder::<MAX_FRAME_SIZE>::new();
+    let mut chunk = [0; 256];
+    while let Ok(n @ 1..) = reader.read(&mut chunk).await {
+        for frame in decoder.decode::<Payload>(&chunk[..n]) {
+            let Ok(envelope) = frame else {
+                continue;
+            };
+            match envelope.payload {
+                Payload::Response(response) => {
+                    let sender = pending.lock().unwrap().waiting.remove(&envelope.sequence);
+                    if let Some(sender) = sender {
+                        let _ = sender.send(response);
+                    }
+                }
+                _ => {
+                    let _ = frames.send(envelope);
+                }
+            }
+        }
+    }
+    // Dropping the senders wakes every request still waiting with `Closed`, and
+    // later requests fail right away instead of waiting for their deadline.
+    let mut pending = pending.lock().unwrap();
+    pending.closed = true;
+    pending.waiting.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_transfer::conversions::MagneticField;
+    use data_transfer::messaging::Message;
+    use tokio::io::{duplex, split, DuplexStream, ReadHalf, WriteHalf};
+
+    const CONFIG: ClientConfig = ClientConfig {
+        attempt_timeout: Duration::from_millis(50),
+        deadline: Duration::from_millis(200),
+    };
+
+    async fn write<T: serde::Serialize>(
+        writer: &mut WriteHalf<DuplexStream>,
+        envelope: Envelope<T>,
+    ) {
+        let mut buffer = [0; MAX_FRAME_SIZE];
+        let frame = encode_frame(&envelope, &mut buffer).unwrap();
+        writer.write_all(frame).await.unwrap();
+    }
+
+    /// Plays the board: answers commands through `respond`, which sees how many
+    /// times the same command has been received, and streams a frame before every answer.
+    fn board<F>(stream: DuplexStream, respond: F)
+    where
+        F: Fn(Command, usize) -> Option<Response> + Send + 'static,
+    {
+        tokio::spawn(async move {
+            let (mut reader, mut writer): (ReadHalf<_>, WriteHalf<_>) = split(stream);
+            let mut decoder = FrameDecoder::<MAX_FRAME_SIZE>::new();
+            let mut attempts = HashMap::<u32, usize>::new();
+            let mut chunk = [0; 256];
+            while let Ok(n @ 1..) = reader.read(&mut chunk).await {
+                let commands: Vec<_> = decoder
+                    .decode::<Command>(&chunk[..n])
+                    .filter_map(Result::ok)
+                    .collect();
+                for envelope in commands {
+                    let attempt = attempts.entry(envelope.sequence).or_default();
+                    *attempt += 1;
+                    let message = Message::new(MagneticField::default());
+                    write(
+                        &mut writer,
+                        Envelope::new(0x0C, 0, 0, Payload::Field(message)),
+                    )
+                    .await;
+                    if let Some(response) = respond(envelope.payload, *attempt) {
+                        let reply =
+                            Envelope::new(0x0C, envelope.sequence, 0, Payload::Response(response));
+                        write(&mut writer, reply).await;
+                    }
+                }
+            }
+        });
+    }
+
+    fn client(
+        respond: impl Fn(Command, usize) -> Option<Response> + Send + 'static,
+    ) -> (Client<WriteHalf<DuplexStream>>, Frames) {
+        let (host, device) = duplex(4096);
+        board(device, respond);
+        let (reader, writer) = split(host);
+        Client::new(reader, writer, CONFIG)
+    }
+
+    #[tokio::test]
+    async fn matches_responses_while_streaming() {
+        let (client, mut frames) = client(|command, _| match command {
+            Command::ReadRegister { address } => Some(Ok(Ack::Register {
+                address,
+                data: [0, address],
+            })),
+            _ => Some(Ok(Ack::Done)),
+        });
+
+        let (first, second) = tokio::join!(
+            client.request(0x0C, Command::ReadRegister { address: 0x02 }),
+            client.request(0x0C, Command::ReadRegister { address: 0x05 }),
+        );
+        assert_eq!(
+            first.unwrap(),
+            Ack::Register {
+                address: 0x02,
+                data: [0, 0x02]
+            }
+        );
+        assert_eq!(
+            second.unwrap(),
+            Ack::Register {
+                address: 0x05,
+                data: [0, 0x05]
+            }
+        );
+
+        for _ in 0..2 {
+            let frame = frames.recv().await.unwrap();
+            assert!(matches!(frame.payload, Payload::Field(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_the_layout_hash() {
+        let (client, _) = client(|command, _| match command {
+            Command::Handshake => Some(Ok(Ack::Handshake {
+                layout_hash: 0xDEAD_BEEF,
+            })),
+            _ => Some(Ok(Ack::Done)),
+        });
+        assert_eq!(client.handshake().await.unwrap(), 0xDEAD_BEEF);
+    }
+
+    #[tokio::test]
+    async fn retries_after_a_lost_response() {
+        let (client, _) = client(|_, attempt| (attempt >= 2).then_some(Ok(Ack::Done)));
+        assert_eq!(client.request(0, Command::Reset).await.unwrap(), Ack::Done);
+    }
+
+    #[tokio::test]
+    async fn gives_up_at_the_deadline() {
+        let (client, _) = client(|_, _| None);
+        let start = Instant::now();
+        let result = client.request(0, Command::StartStreaming).await;
+        assert!(matches!(result, Err(ClientError::Timeout)));
+        assert!(start.elapsed() >= CONFIG.deadline);
+    }
+
+    #[tokio::test]
+    async fn reports_command_errors() {
+        let (client, _) = client(|_, _| Some(Err(CommandError::InvalidRegister)));
+        let result = client
+            .request(0x0C, Command::ReadRegister { address: 0x40 })
+            .await;
+        assert!(matches!(
+            result,
+            Err(ClientError::Command(CommandError::InvalidRegister))
+        ));
+    }
+
+    #[tokio::test]
+    async fn ends_frames_when_the_board_disconnects() {
+        let (host, device) = duplex(4096);
+        let (reader, writer) = split(host);
+        let (_client, mut frames) = Client::new(reader, writer, CONFIG);
+        drop(device);
+        assert!(frames.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn fails_pending_requests_when_the_board_disconnects() {
+        let (host, device) = duplex(4096);
+        let (reader, writer) = split(host);
+        let (client, _) = Client::new(reader, writer, CONFIG);
+        drop(device);
+        let result = client.request(0, Command::Reset).await;
+        assert!(matches!(
+            result,
+            Err(ClientError::Closed | ClientError::Io(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn fails_requests_after_the_reader_ends_without_waiting() {
+        // A sink accepts every command, so only the closed reader can fail them.
+        let (client, mut frames) = Client::new(tokio::io::empty(), tokio::io::sink(), CONFIG);
+        assert!(frames.recv().await.is_none());
+        let start = Instant::now();
+        let result = client.request(0, Command::Reset).await;
+        assert!(matches!(result, Err(ClientError::Closed)));
+        assert!(start.elapsed() < CONFIG.attempt_timeout);
+    }
+}
@@ -0,0 +1,390 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use data_transfer::conversions::{FieldVector, MagneticField};
+use data_transfer::messaging::{Envelope, Payload};
+use serde::Serialize;
+
+use crate::client::Frames;
+use crate::Board;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    /// One JSON object per line
+    Ndjson,
+}
+
+/// One reading of one sensor, flattened for analysis tools.
+///
+/// Axes the sensor did not measure are left empty and flagged as missing. When the
+/// sensor could not be read, or its counts could not be decoded, every value is left
+/// empty and `error` says why.
+#[derive(Debug, Serialize)]
+struct Row {
+    /// Board time of the reading, in microseconds since it booted.
+    timestamp_us: u64,
+    sensor: u8,
+    /// Only known for sensors in the layout, in mm.
+    pos_x: Option<f32>,
+    pos_y: Option<f32>,
+    pos_z: Option<f32>,
+    bx_ut: Option<f64>,
+    by_ut: Option<f64>,
+    bz_ut: Option<f64>,
+    temp_c: Option<f64>,
+    missing_bx: bool,
+    missing_by: bool,
+    missing_bz: bool,
+    missing_temp: bool,
+    error: Option<String>,
+}
+
+impl Row {
+    fn new(
+        timestamp_us: u64,
+        sensor: u8,
+        position: Option<[f32; 3]>,
+        field: &MagneticField,
+    ) -> Self {
+        let FieldVector {
+            x: bx_ut,
+            y: by_ut,
+            z: bz_ut,
+        } = field.vector();
+        let temp_c = field.t.map(|temp| temp.celsius());
+        Self {
+            timestamp_us,
+            sensor,
+            pos_x: position.map(|[x, _, _]| x),
+            pos_y: position.map(|[_, y, _]| y),
+            pos_z: position.map(|[_, _, z]| z),
+            bx_ut,
+            by_ut,
+            bz_ut,
+            temp_c,
+            missing_bx: bx_ut.is_none(),
+            missing_by: by_ut.is_none(),
+            missing_bz: bz_ut.is_none(),
+            missing_temp: temp_c.is_none(),
+            error: None,
+        }
+    }
+
+    fn error(timestamp_us: u64, sensor: u8, position: Option<[f32; 3]>, error: String) -> Self {
+        Self {
+            timestamp_us,
+            sensor,
+            pos_x: position.map(|[x, _, _]| x),
+            pos_y: position.map(|[_, y, _]| y),
+            pos_z: position.map(|[_, _, z]| z),
+            bx_ut: None,
+            by_ut: None,
+            bz_ut: None,
+            temp_c: None,
+            missing_bx: false,
+            missing_by: false,
+            missing_bz: false,
+            missing_temp: false,
+            error: Some(error),
+        }
+    }
+}
+
+fn rows(envelope: &Envelope<Payload>, board: &Board) -> Vec<Row> {
+    let timestamp_us = envelope.timestamp_us;
+    let row = |sensor: u8, field: MagneticField| {
+        Row::new(
+            timestamp_us,
+            sensor,
+            board.position(sensor),
+            &board.apply(sensor, field),
+        )
+    };
+    let error =
+        |sensor: u8, error: String| Row::error(timestamp_us, sensor, board.position(sensor), error);
+    match &envelope.payload {
+        Payload::Field(message) => vec![row(envelope.sensor, message.field)],
+        Payload::Raw(raw) => vec![match raw.to_field() {
+            Ok(field) => row(envelope.sensor, field),
+            Err(err) => error(envelope.sensor, err.to_string()),
+        }],
+        Payload::Board(frame) => frame
+            .iter()
+            .map(|(index, field)| match board.layout.address(index) {
+                Some(sensor) => row(sensor, *field),
+                None => error(
+                    envelope.sensor,
+                    format!("board frame index {} is not in the layout", index),
+                ),
+            })
+            .collect(),
+        Payload::Error(err) => vec![error(envelope.sensor, format!("{:?}", err))],
+        Payload::Response(_) => Vec::new(),
+    }
+}
+
+enum RowWriter {
+    Csv(Box<csv::Writer<Box<dyn Write>>>),
+    Ndjson(Box<dyn Write>),
+}
+
+impl RowWriter {
+    fn write(&mut self, row: &Row) -> io::Result<()> {
+        match self {
+            RowWriter::Csv(writer) => writer.serialize(row)?,
+            RowWriter::Ndjson(writer) => {
+                serde_json::to_writer(&mut *writer, row)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            RowWriter::Csv(writer) => writer.flush(),
+            RowWriter::Ndjson(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Writes a row per reading to `output`, or to stdout, until the frames end or Ctrl-C is pressed.
+pub async fn export(
+    frames: &mut Frames,
+    board: &Board,
+    format: ExportFormat,
+    output: Option<&Path>,
+) -> io::Result<()> {
+    let output: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let mut writer = match format {
+        ExportFormat::Csv => RowWriter::Csv(Box::new(csv::Writer::from_writer(output))),
+        ExportFormat::Ndjson => RowWriter::Ndjson(output),
+    };
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        let envelope = tokio::select! {
+            envelope = frames.recv() => envelope,
+            _ = &mut ctrl_c => None,
+        };
+        let Some(envelope) = envelope else {
+            break;
+        };
+        for row in rows(&envelope, board) {
+            writer.write(&row)?;
+        }
+        // Flushed per frame so the output can be followed while streaming live.
+        writer.flush()?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_transfer::conversions::{MagneticBits, MagneticValue, TempValue};
+    use data_transfer::memory::{
+        Gain, HallConf, Res3D, Resolution, SensTc, SensorConfig, TempRef, TemperatureCompensation,
+    };
+    use data_transfer::messaging::{BoardFrame, Message, RawMessage, SensorError};
+
+    use crate::layout::Layout;
+
+    fn board() -> Board {
+        Board {
+            layout: Layout::default(),
+            calibration: Default::default(),
+            baseline: Default::default(),
+        }
+    }
+
+    fn field(x: Option<f64>, z: Option<f64>, celsius: Option<f64>) -> MagneticField {
+        MagneticField {
+            x: x.map(MagneticValue::uT),
+            y: None,
+            z: z.map(MagneticValue::uT),
+            t: celsius.map(TempValue::Celsius),
+        }
+    }
+
+    fn raw(x: u16) -> RawMessage {
+        let config = SensorConfig {
+            resolution: Res3D {
+                x: Resolution::BIT18,
+                y: Resolution::BIT18,
+                z: Resolution::BIT18,
+            },
+            gain: Gain::SEVEN,
+            temperature_compensation: TemperatureCompensation::Disabled,
+            hall_configuration: HallConf::FOURPHASE,
+            temp_ref: TempRef::TYPICAL,
+            sens_tc: SensTc { low: 0, high: 0 },
+        };
+        RawMessage::new(
+            MagneticBits::new(Some(x.to_be_bytes()), None, None, None),
+            config,
+        )
+    }
+
+    #[test]
+    fn field_rows_flag_the_missing_axes() {
+        let envelope = Envelope::new(
+            0x0D,
+            1,
+            1234,
+            Payload::Field(Message::new(field(Some(10.0), None, Some(25.0)))),
+        );
+        let output = rows(&envelope, &board());
+        assert_eq!(output.len(), 1);
+        let row = &output[0];
+        assert_eq!((row.timestamp_us, row.sensor), (1234, 0x0D));
+        assert_eq!(
+            (row.pos_x, row.pos_y, row.pos_z),
+            (Some(6.75), Some(-2.25), Some(0.0))
+        );
+        assert_eq!((row.bx_ut, row.by_ut, row.bz_ut), (Some(10.0), None, None));
+        assert_eq!(row.temp_c, Some(25.0));
+        assert_eq!(
+            (
+                row.missing_bx,
+                row.missing_by,
+                row.missing_bz,
+                row.missing_temp
+            ),
+            (false, true, true, false)
+        );
+        assert_eq!(row.error, None);
+    }
+
+    #[test]
+    fn board_rows_use_the_layout_addresses() {
+        let mut frame = BoardFrame::new();
+        frame.set(0, field(Some(1.0), Some(2.0), None));
+        frame.set(15, field(None, Some(3.0), None));
+        let envelope = Envelope::new(0, 1, 0, Payload::Board(frame));
+        let output = rows(&envelope, &board());
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[0].sensor, 0x0C);
+        assert_eq!((output[0].bx_ut, output[0].bz_ut), (Some(1.0), Some(2.0)));
+        assert!(output[0].missing_temp);
+        assert_eq!(output[1].sensor, 0x1B);
+        assert_eq!(
+            (output[1].pos_x, output[1].pos_y),
+            (Some(-6.75), Some(6.75))
+        );
+        assert!(output[1].missing_bx && !output[1].missing_bz);
+    }
+
+    #[test]
+    fn board_rows_outside_the_layout_are_errors() {
+        let mut board = board();
+        board.layout.sensors.truncate(1);
+        let mut frame = BoardFrame::new();
+        frame.set(0, field(Some(1.0), None, None));
+        frame.set(3, field(Some(2.0), None, None));
+        let envelope = Envelope::new(0, 1, 0, Payload::Board(frame));
+        let output = rows(&envelope, &board);
+        assert_eq!(output[0].sensor, 0x0C);
+        assert_eq!(output[0].error, None);
+        assert_eq!(output[1].bx_ut, None);
+        assert_eq!(
+            output[1].error.as_deref(),
+            Some("board frame index 3 is not in the layout")
+        );
+    }
+
+    #[test]
+    fn raw_rows_are_converted_or_report_the_error() {
+        let envelope = Envelope::new(0x0C, 1, 0, Payload::Raw(raw(0x8000)));
+        let output = rows(&envelope, &board());
+        assert_eq!(output[0].bx_ut, Some(0.0));
+        assert!(!output[0].missing_bx && output[0].missing_by);
+        assert_eq!(output[0].error, None);
+
+        let envelope = Envelope::new(0x0C, 2, 0, Payload::Raw(raw(0x0000)));
+        let output = rows(&envelope, &board());
+        assert_eq!(output.len(), 1);
+        let row = &output[0];
+        assert_eq!(
+            (row.bx_ut, row.by_ut, row.bz_ut, row.temp_c),
+            (None, None, None, None)
+        );
+        assert!(!(row.missing_bx || row.missing_by || row.missing_bz || row.missing_temp));
+        assert_eq!(
+            row.error.as_deref(),
+            Some("X counts -32768 are out of range at resolution BIT18")
+        );
+    }
+
+    #[test]
+    fn sensor_errors_are_reported() {
+        let envelope = Envelope::new(0x0E, 1, 0, Payload::Error(SensorError::Timeout));
+        let output = rows(&envelope, &board());
+        assert_eq!(output[0].sensor, 0x0E);
+        assert_eq!(output[0].error.as_deref(), Some("Timeout"));
+    }
+
+    const COLUMNS: [&str; 14] = [
+        "timestamp_us",
+        "sensor",
+        "pos_x",
+        "pos_y",
+        "pos_z",
+        "bx_ut",
+        "by_ut",
+        "bz_ut",
+        "temp_c",
+        "missing_bx",
+        "missing_by",
+        "missing_bz",
+        "missing_temp",
+        "error",
+    ];
+
+    fn row() -> Row {
+        let envelope = Envelope::new(
+            0x0C,
+            1,
+            42,
+            Payload::Field(Message::new(field(Some(1.5), None, None))),
+        );
+        rows(&envelope, &board()).remove(0)
+    }
+
+    #[test]
+    fn csv_has_a_column_per_field() {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(row()).unwrap();
+        let text = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next().unwrap(), COLUMNS.join(","));
+        assert_eq!(
+            lines.next().unwrap(),
+            "42,12,6.75,-6.75,0.0,1.5,,,,false,true,true,true,"
+        );
+    }
+
+    #[test]
+    fn ndjson_has_a_key_per_field() {
+        let line = serde_json::to_string(&row()).unwrap();
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        let object = value.as_object().unwrap();
+        assert_eq!(object.len(), COLUMNS.len());
+        for column in COLUMNS {
+            assert!(object.contains_key(column), "{}", column);
+        }
+        assert_eq!(object["bx_ut"], 1.5);
+        assert!(object["by_ut"].is_null());
+        assert_eq!(object["missing_by"], true);
+    }
+}
@@ -0,0 +1,160 @@
+use std::{fs, io, path::Path};
+
+use data_transfer::layout::{BoardLayout, SensorPlacement, BOARD_LAYOUT};
+use data_transfer::recording::RecordingHeader;
+use serde::{Deserialize, Serialize};
+
+/// The layout of the board, in the order of the indices of a board frame.
+///
+/// Loaded from a TOML file with one `[[sensors]]` table per sensor:
+///
+/// ```toml
+/// [[sensors]]
+/// address = 0x0C
+/// interrupt = "PB0"
+/// position = [6.75, -6.75, 0.0]
+/// orientation = { x = "+x", y = "+y", z = "+z" }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Layout {
+    pub sensors: Vec<SensorPlacement>,
+}
+
+impl Layout {
+    /// Loads the layout at `path`, or the layout the firmware is built with.
+    pub fn load(path: Option<&Path>) -> io::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let layout: Self = toml::from_str(&fs::read_to_string(path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if let Some(sensor) = layout
+            .sensors
+            .iter()
+            .find(|sensor| !sensor.orientation.is_valid())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "sensor {:#04x} has two axes along the same board axis",
+                    sensor.address
+                ),
+            ));
+        }
+        Ok(layout)
+    }
+
+    /// The layout a recording was made with. A layout at `path` is only used when it
+    /// is the recorded one, so readings are not replayed at the wrong positions.
+    pub fn for_recording(path: Option<&Path>, header: &RecordingHeader) -> io::Result<Self> {
+        let recorded = Self {
+            sensors: header.layout.clone(),
+        };
+        let Some(path) = path else {
+            return Ok(recorded);
+        };
+        let layout = Self::load(Some(path))?;
+        if layout.hash() != recorded.hash() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} is not the layout of the recording (hash {:#010x}, recorded {:#010x})",
+                    path.display(),
+                    layout.hash(),
+                    recorded.hash()
+                ),
+            ));
+        }
+        Ok(layout)
+    }
+
+    pub fn board(&self) -> BoardLayout<'_> {
+        BoardLayout::new(&self.sensors)
+    }
+
+    pub fn hash(&self) -> u32 {
+        self.board().hash()
+    }
+
+    pub fn sensor(&self, address: u8) -> Option<&SensorPlacement> {
+        self.board().sensor(address)
+    }
+
+    /// Address of the sensor at `index` in a board frame, or `None` if the layout
+    /// has no sensor there.
+    pub fn address(&self, index: usize) -> Option<u8> {
+        self.sensors.get(index).map(|sensor| sensor.address)
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            sensors: BOARD_LAYOUT.sensors.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_transfer::layout::{BoardAxis, PinLabel};
+    use data_transfer::messaging::StreamFormat;
+
+    #[test]
+    fn loads_the_layout_from_toml() {
+        let layout: Layout = toml::from_str(
+            r#"
+            [[sensors]]
+            address = 0x0D
+            interrupt = "PB14"
+            position = [6.75, -2.25, 0.0]
+
+            [[sensors]]
+            address = 0x0C
+            interrupt = "PB0"
+            position = [6.75, -6.75, 0.5]
+            orientation = { x = "-y", y = "+x", z = "+z" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(layout.sensors.len(), 2);
+        assert_eq!(layout.address(0), Some(0x0D));
+        assert_eq!(layout.address(1), Some(0x0C));
+        assert_eq!(layout.address(2), None);
+        let sensor = layout.sensor(0x0C).unwrap();
+        assert_eq!(sensor.interrupt, PinLabel::new('B', 0));
+        assert_eq!(sensor.position, [6.75, -6.75, 0.5]);
+        assert_eq!(sensor.orientation.x, BoardAxis::MinusY);
+        assert_eq!(layout.sensor(0x0D).unwrap().orientation, Default::default());
+    }
+
+    #[test]
+    fn toml_round_trip_keeps_the_hash() {
+        let layout = Layout::default();
+        let text = toml::to_string(&layout).unwrap();
+        let loaded: Layout = toml::from_str(&text).unwrap();
+        assert_eq!(loaded.hash(), BOARD_LAYOUT.hash());
+    }
+
+    #[test]
+    fn recordings_are_replayed_with_their_layout() {
+        let recorded = Layout::default().sensors[..4].to_vec();
+        let header =
+            RecordingHeader::new(0, StreamFormat::Microtesla, recorded.clone(), Vec::new());
+        let layout = Layout::for_recording(None, &header).unwrap();
+        assert_eq!(layout.sensors, recorded);
+
+        let path = std::env::temp_dir().join(format!("layout-{}.toml", std::process::id()));
+        let other = Layout::default();
+        fs::write(&path, toml::to_string(&other).unwrap()).unwrap();
+        let err = Layout::for_recording(Some(&path), &header).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let header = RecordingHeader::new(0, StreamFormat::Microtesla, other.sensors, Vec::new());
+        let layout = Layout::for_recording(Some(&path), &header);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(layout.unwrap().hash(), BOARD_LAYOUT.hash());
+    }
+}
@@ -1,30 +1,85 @@
-use std::{
-    io::{self, Write},
-    thread::sleep,
-    time::Duration,
-};
+mod baseline;
+mod calibration;
+mod client;
+mod export;
+mod layout;
+mod record;
+mod transport;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
 
+use clap::{Parser, Subcommand};
 use crossterm::{
     cursor,
-    event::{self, Event},
+    event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute, queue,
     style::{self, Stylize},
     terminal,
 };
-use data_transfer::conversions::{MagneticField, MagneticValue, TempValue};
-use postcard::from_bytes;
-use ratatui::{text::Text, widgets::Row, Frame};
-use serialport::SerialPort;
+use data_transfer::conversions::{Axis, DecodeError, MagneticField};
+use data_transfer::messaging::{Command, Envelope, Payload, StreamFormat, BOARD_SENSORS};
+use data_transfer::recording::RecordingReader;
+use futures::StreamExt;
 
-struct Model {
-    pub fields: MagneticField,
+use crate::baseline::{BoardBaseline, BoardCapture};
+use crate::calibration::BoardCalibration;
+use crate::client::{Client, ClientConfig, Frames};
+use crate::export::ExportFormat;
+use crate::layout::Layout;
+use crate::transport::TransportArgs;
+
+#[derive(Parser, Debug)]
+#[command(about = "Shows the readings streamed by the magnetic board")]
+struct Cli {
+    #[command(flatten)]
+    transport: TransportArgs,
+    /// Stream raw sensor counts instead of microtesla
+    #[arg(long)]
+    raw: bool,
+    /// Calibration of the sensors, applied to the readings if the file exists
+    #[arg(long, value_name = "PATH", default_value = "calibration.json")]
+    calibration: PathBuf,
+    /// TOML description of the board, instead of the layout built into the firmware
+    #[arg(long, value_name = "PATH")]
+    layout: Option<PathBuf>,
+    /// Readings averaged per sensor when capturing the baseline
+    #[arg(long, value_name = "READINGS", default_value_t = 100)]
+    baseline_readings: u32,
+    #[command(subcommand)]
+    mode: Option<Mode>,
 }
 
-enum Message {
-    RecievedField(MagneticField),
+#[derive(Subcommand, Debug)]
+enum Mode {
+    /// Show the readings in the terminal (default)
+    ///
+    /// Press b with no magnet near the board to capture the baseline, which is then
+    /// subtracted from every reading, and q to quit.
+    Monitor,
+    /// Save every frame to a recording that can be replayed with --replay
+    Record { path: PathBuf },
+    /// Write one row per sensor reading, for analysis tools
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// File to write to instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Fit a calibration to every sensor while the board is turned in every direction,
+    /// and save it to the --calibration file
+    Calibrate {
+        /// Strength of the local Earth field in µT, to also correct the scale
+        #[arg(long, value_name = "MICROTESLA")]
+        field: Option<f64>,
+    },
 }
 
-fn main() -> io::Result<()> {
+#[tokio::main]
+async fn main() -> io::Result<()> {
     //let mut terminal = ratatui::init();
     //loop {
     //terminal.draw(draw).expect("failed to draw frame");
@@ -33,89 +88,343 @@ fn main() -> io::Result<()> {
     //}
     //}
     //ratatui::restore();
+    let cli = Cli::parse();
+    // The baseline is kept next to the calibration it was captured with.
+    let baseline_path = cli.calibration.with_file_name(BASELINE_FILE);
+    let layout = match cli.transport.replay_path() {
+        Some(path) => {
+            let recording = RecordingReader::new(BufReader::new(File::open(path)?))?;
+            Layout::for_recording(cli.layout.as_deref(), recording.header())?
+        }
+        None => Layout::load(cli.layout.as_deref())?,
+    };
+    let mut board = Board {
+        layout,
+        calibration: BoardCalibration::load(&cli.calibration)?,
+        baseline: BoardBaseline::load(&baseline_path)?,
+    };
+    let transport = cli.transport.transport();
+    let connection = transport.open().await?;
+    let (client, mut frames) = Client::new(
+        connection.reader,
+        connection.writer,
+        ClientConfig::default(),
+    );
+
+    let format = match cli.raw {
+        true => StreamFormat::Raw,
+        false => StreamFormat::Microtesla,
+    };
+    if transport.accepts_commands() {
+        match client.handshake().await {
+            Ok(hash) if hash == board.layout.hash() => {}
+            Ok(hash) => eprintln!(
+                "The firmware was built for another layout (hash {:#010x}, expected {:#010x})",
+                hash,
+                board.layout.hash()
+            ),
+            Err(err) => eprintln!("Handshake failed: {}", err),
+        }
+        if let Err(err) = client.request(0, Command::SetFormat(format)).await {
+            eprintln!("Failed to select the stream format: {}", err);
+        }
+    }
+
+    match cli.mode.unwrap_or(Mode::Monitor) {
+        Mode::Monitor => {
+            let capture = BaselineSettings {
+                readings: cli.baseline_readings,
+                path: &baseline_path,
+            };
+            monitor(&mut frames, &mut board, capture).await
+        }
+        Mode::Record { path } => {
+            let accepts_commands = transport.accepts_commands();
+            record::record(
+                &client,
+                &mut frames,
+                &board.layout,
+                accepts_commands,
+                format,
+                &path,
+            )
+            .await
+        }
+        Mode::Export { format, output } => {
+            export::export(&mut frames, &board, format, output.as_deref()).await
+        }
+        Mode::Calibrate { field } => {
+            calibration::calibrate(&mut frames, &board.layout, field, &cli.calibration).await
+        }
+    }
+}
+
+const BASELINE_FILE: &str = "baseline.json";
+
+/// What the host knows about the board: where its sensors are, and how to correct them.
+struct Board {
+    layout: Layout,
+    calibration: BoardCalibration,
+    baseline: BoardBaseline,
+}
+
+impl Board {
+    /// Calibrates the reading of `sensor`, subtracts its baseline and turns it
+    /// along the board axes.
+    fn apply(&self, sensor: u8, field: MagneticField) -> MagneticField {
+        let field = self
+            .baseline
+            .apply(sensor, self.calibration.apply(sensor, field));
+        match self.layout.sensor(sensor) {
+            Some(placement) => placement.orientation.field_to_board(field),
+            None => field,
+        }
+    }
+
+    fn position(&self, sensor: u8) -> Option<[f32; 3]> {
+        self.layout
+            .sensor(sensor)
+            .map(|placement| placement.position)
+    }
+}
+
+/// Why the readings of a frame could not be used.
+#[derive(Debug)]
+enum ReadingError {
+    Decode(DecodeError),
+    /// A board frame holds a reading at an index the layout has no sensor for.
+    NotInLayout(usize),
+}
+
+impl fmt::Display for ReadingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadingError::Decode(err) => write!(f, "{}", err),
+            ReadingError::NotInLayout(index) => {
+                write!(f, "board frame index {} is not in the layout", index)
+            }
+        }
+    }
+}
+
+impl From<DecodeError> for ReadingError {
+    fn from(value: DecodeError) -> Self {
+        Self::Decode(value)
+    }
+}
+
+/// The uncorrected field of every sensor in the frame, or why they cannot be used.
+fn readings(
+    envelope: &Envelope<Payload>,
+    layout: &Layout,
+) -> Result<Vec<(u8, MagneticField)>, ReadingError> {
+    Ok(match &envelope.payload {
+        Payload::Field(message) => vec![(envelope.sensor, message.field)],
+        Payload::Raw(raw) => vec![(envelope.sensor, raw.to_field()?)],
+        Payload::Board(frame) => frame
+            .iter()
+            .map(|(index, field)| {
+                let sensor = layout
+                    .address(index)
+                    .ok_or(ReadingError::NotInLayout(index))?;
+                Ok((sensor, *field))
+            })
+            .collect::<Result<_, ReadingError>>()?,
+        Payload::Response(_) | Payload::Error(_) => Vec::new(),
+    })
+}
+
+struct BaselineSettings<'a> {
+    readings: u32,
+    path: &'a Path,
+}
+
+async fn monitor(
+    frames: &mut Frames,
+    board: &mut Board,
+    baseline: BaselineSettings<'_>,
+) -> io::Result<()> {
     let mut stdout = std::io::stdout();
-    let p = serialport::available_ports().expect("Serial Port not found");
-    let baud_rate = 115200;
-    let port_builder = serialport::new(p.first().unwrap().port_name.clone(), baud_rate);
-    let mut port = port_builder.open().unwrap();
-    let _ = port.write(&[1; 8]);
-    //let mut buffer = [0; 100000];
     execute!(stdout, terminal::Clear(terminal::ClearType::All))?;
+    // Raw mode delivers key presses without waiting for enter. Without a terminal,
+    // like when the output is piped, the readings are shown without key presses.
+    let mut events = terminal::enable_raw_mode().ok().map(|_| EventStream::new());
+    let result = monitor_loop(&mut stdout, frames, board, baseline, events.as_mut()).await;
+    if events.is_some() {
+        terminal::disable_raw_mode()?;
+    }
+    result
+}
+
+/// The next key press, or never if key presses cannot be read.
+async fn key_press(events: Option<&mut EventStream>) -> io::Result<KeyEvent> {
+    if let Some(events) = events {
+        while let Some(event) = events.next().await {
+            match event? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => return Ok(key),
+                _ => {}
+            }
+        }
+    }
+    std::future::pending().await
+}
+
+async fn monitor_loop<W: Write>(
+    stdout: &mut W,
+    frames: &mut Frames,
+    board: &mut Board,
+    baseline: BaselineSettings<'_>,
+    mut events: Option<&mut EventStream>,
+) -> io::Result<()> {
+    let mut capture: Option<BoardCapture> = None;
     loop {
-        let val = data_transfer::messaging::Message::read(&mut port).map(|msg| msg);
-        match val {
-            Ok(msg) => {
-                let (bx, by, bz, temp) = (
-                    msg.field.x.unwrap_or(MagneticValue::uT(0.0)),
-                    msg.field.y.unwrap_or(MagneticValue::uT(0.0)),
-                    msg.field.z.unwrap_or(MagneticValue::uT(0.0)),
-                    msg.field.t.unwrap_or(TempValue::Celsius(0.0)),
-                );
-                let (x, y, z) = msg.position;
-                let val = format!(
-                    "x: {:.2}\ty: {:.2}\tz: {:.2}\nBx: {:.3}\tBy: {:.3}\tBz: {:.3}\tTemp: {:.3}\n",
-                    x,
-                    y,
-                    z,
-                    bx.value(),
-                    by.value(),
-                    bz.value(),
-                    temp.value()
-                );
-                queue!(
-                    stdout,
-                    cursor::MoveToPreviousLine(2),
-                    style::PrintStyledContent(val.magenta())
-                )?;
+        tokio::select! {
+            envelope = frames.recv() => {
+                let Some(envelope) = envelope else {
+                    return Ok(());
+                };
+                if let Some(in_progress) = &mut capture {
+                    // Readings that cannot be used are left out, display reports them.
+                    for (sensor, field) in readings(&envelope, &board.layout).unwrap_or_default() {
+                        in_progress.add(sensor, &board.calibration.apply(sensor, field));
+                    }
+                    if in_progress.is_done() {
+                        board.baseline = in_progress.baseline();
+                        board.baseline.save(baseline.path)?;
+                        capture = None;
+                        status(stdout, "Baseline captured")?;
+                    } else {
+                        let waiting: Vec<String> = in_progress
+                            .waiting()
+                            .map(|sensor| format!("{:#04x}", sensor))
+                            .collect();
+                        let message =
+                            format!("Capturing the baseline, waiting for {}", waiting.join(", "));
+                        status(stdout, &message)?;
+                    }
+                }
+                display(stdout, &envelope, board)?;
             }
-            Err(val) => {
-                println!("{:#?}", val);
+            key = key_press(events.as_deref_mut()) => {
+                let key = key?;
+                match key.code {
+                    KeyCode::Char('b') => {
+                        let sensors = board.layout.sensors.iter().map(|sensor| sensor.address);
+                        capture = Some(BoardCapture::new(baseline.readings, sensors));
+                        status(stdout, "Capturing the baseline, keep magnets away from the board")?;
+                    }
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        return Ok(())
+                    }
+                    _ => {}
+                }
             }
-        };
-        port.clear(serialport::ClearBuffer::Input);
-        sleep(Duration::new(0, 100000000));
+        }
     }
 }
 
-fn draw(frame: &mut Frame) {
-    let text = Text::raw("Hello World!");
-    let block = ratatui::widgets::List::new(["test", "test2"]);
-    let rows = [Row::new(vec!["X", "Y", "Z", "TEMP"])];
-    let table = ratatui::widgets::Table::new(rows, [15, 15, 15, 15]);
-    frame.render_widget(table, frame.area());
-}
-
-fn test() {
-    let p = serialport::available_ports().expect("Serial Port not found");
-    let baud_rate = 115200;
-    let port_builder = serialport::new(p.first().unwrap().port_name.clone(), baud_rate);
-    let mut port = port_builder.open().unwrap();
-    let _ = port.write(&[1; 8]);
-    let mut buffer = [0; 100000];
-    let val = postcard::from_io::<MagneticField, _>((port, &mut buffer));
-    match val {
-        Ok((field, _)) => print!("{:#?}", field),
-        Err(val) => print!("{:#?}", val),
-    };
+/// Shows `message` on the first line of the terminal.
+fn status<W: Write>(stdout: &mut W, message: &str) -> io::Result<()> {
+    queue!(
+        stdout,
+        cursor::SavePosition,
+        cursor::MoveTo(0, 0),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        style::PrintStyledContent(message.yellow()),
+        cursor::RestorePosition
+    )?;
+    stdout.flush()
+}
+
+/// Overwrites the last `lines` lines with `block`.
+fn print_block<W: Write>(stdout: &mut W, lines: u16, block: String) -> io::Result<()> {
+    // Raw mode does not return the carriage on a line feed.
+    let block = block.replace('\n', "\r\n");
+    queue!(
+        stdout,
+        cursor::MoveToPreviousLine(lines),
+        style::PrintStyledContent(block.magenta())
+    )
 }
 
-fn update(model: &Model, msg: Message) -> Model {
-    match msg {
-        Message::RecievedField(field) => Model { fields: field },
+fn display<W: Write>(
+    stdout: &mut W,
+    envelope: &Envelope<Payload>,
+    board: &Board,
+) -> io::Result<()> {
+    match envelope.payload {
+        Payload::Field(msg) => {
+            let field = board.apply(envelope.sensor, msg.field);
+            let position = match board.position(envelope.sensor) {
+                Some([x, y, z]) => format!("x: {:.2}\ty: {:.2}\tz: {:.2}", x, y, z),
+                None => "not in the layout".to_string(),
+            };
+            let val = format!(
+                "Sensor: {:#04x}\tSeq: {}\t{}\n{}\n",
+                envelope.sensor,
+                envelope.sequence,
+                position,
+                format_field(&field),
+            );
+            print_block(stdout, 2, val)?;
+        }
+        Payload::Raw(raw) => match raw.to_field() {
+            Ok(field) => {
+                let field = board.apply(envelope.sensor, field);
+                let val = format!(
+                    "Sensor: {:#04x}\tSeq: {}\t(raw)\n{}\n",
+                    envelope.sensor,
+                    envelope.sequence,
+                    format_field(&field),
+                );
+                print_block(stdout, 2, val)?;
+            }
+            Err(err) => {
+                let message = format!(
+                    "Sensor {:#04x}: could not decode the reading, {}",
+                    envelope.sensor, err
+                );
+                status(stdout, &message)?;
+            }
+        },
+        Payload::Error(err) => {
+            let val = format!(
+                "Sensor: {:#04x}\tSeq: {}\nCould not be read: {:?}\n",
+                envelope.sensor, envelope.sequence, err,
+            );
+            print_block(stdout, 2, val)?;
+        }
+        // Responses are consumed by the client.
+        Payload::Response(_) => {}
+        Payload::Board(frame) => {
+            let mut val = format!("Board\tSeq: {}\n", envelope.sequence);
+            for index in 0..BOARD_SENSORS {
+                match (frame.get(index), board.layout.address(index)) {
+                    (Some(field), Some(sensor)) => {
+                        let field = board.apply(sensor, *field);
+                        val += &format!("{:>2}: {}\n", index, format_field(&field))
+                    }
+                    (Some(_), None) => val += &format!("{:>2}: not in the layout\n", index),
+                    (None, _) => val += &format!("{:>2}: -\n", index),
+                }
+            }
+            print_block(stdout, 1 + BOARD_SENSORS as u16, val)?;
+        }
     }
+    stdout.flush()
 }
 
-fn view(model: &Model, frame: &mut Frame) {
-    let field = model.fields;
-    let [x, y, z] = [field.x, field.y, field.z].map(|field| {
-        field.map_or("0".to_string(), |val| match val {
-            data_transfer::conversions::MagneticValue::uT(x) => x.to_string(),
+/// Formats a reading, showing `-` for anything that was not measured.
+fn format_field(field: &MagneticField) -> String {
+    let vector = field.vector();
+    let [bx, by, bz] = [Axis::X, Axis::Y, Axis::Z].map(|axis| {
+        vector.get(axis).map_or("-".to_string(), |value| {
+            format!("{:.3}", value.microtesla())
         })
     });
-    let rows = [
-        Row::new(vec!["X", "Y", "Z", "TEMP"]),
-        Row::new(vec![x, y, z]),
-    ];
-    let table = ratatui::widgets::Table::new(rows, [15, 15, 15, 15]);
-    frame.render_widget(table, frame.area())
+    let temp = field
+        .t
+        .map_or("-".to_string(), |temp| format!("{:.3}", temp.celsius()));
+    format!("Bx: {}\tBy: {}\tBz: {}\tTemp: {}", bx, by, bz, temp)
 }
@@ -0,0 +1,133 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use data_transfer::memory::{Register, SensorConfig};
+use data_transfer::messaging::{Command, StreamFormat};
+use data_transfer::recording::{RecordedFrame, RecordingHeader, RecordingWriter, SensorInfo};
+use futures::future::join_all;
+use tokio::{io::AsyncWrite, time::Instant};
+
+use crate::client::{Client, ClientError, Frames};
+use crate::layout::Layout;
+
+/// Writes every frame to a recording at `path` until the board disconnects or Ctrl-C is pressed.
+///
+/// The header keeps `layout`, so the recording can be replayed with the geometry
+/// it was made with. When the board accepts commands, streaming is paused while
+/// the configuration of each sensor is read for the header, so every recorded
+/// frame follows it.
+pub async fn record<W: AsyncWrite + Unpin>(
+    client: &Client<W>,
+    frames: &mut Frames,
+    layout: &Layout,
+    accepts_commands: bool,
+    format: StreamFormat,
+    path: &Path,
+) -> io::Result<()> {
+    let mut sensors = Vec::new();
+    if accepts_commands {
+        if let Err(err) = client.request(0, Command::StopStreaming).await {
+            eprintln!("Failed to pause streaming: {}", err);
+        }
+        sensors = read_sensors(client, layout).await;
+        while frames.try_recv().is_ok() {}
+    }
+
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let header = RecordingHeader::new(
+        started_at.as_micros() as u64,
+        format,
+        layout.sensors.clone(),
+        sensors,
+    );
+    let mut recording = RecordingWriter::new(BufWriter::new(File::create(path)?), &header)?;
+
+    if accepts_commands {
+        if let Err(err) = client.request(0, Command::StartStreaming).await {
+            eprintln!("Failed to resume streaming: {}", err);
+        }
+    }
+
+    let start = Instant::now();
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut count = 0;
+    loop {
+        let envelope = tokio::select! {
+            envelope = frames.recv() => envelope,
+            _ = &mut ctrl_c => None,
+        };
+        let Some(envelope) = envelope else {
+            break;
+        };
+        let received_us = start.elapsed().as_micros() as u64;
+        recording.write(&RecordedFrame {
+            received_us,
+            envelope,
+        })?;
+        count += 1;
+        eprint!("\rRecorded {} frames", count);
+    }
+    eprintln!();
+    recording.flush()
+}
+
+/// Reads the configuration of every sensor of `layout` that answers.
+async fn read_sensors<W: AsyncWrite + Unpin>(
+    client: &Client<W>,
+    layout: &Layout,
+) -> Vec<SensorInfo> {
+    let addresses = layout.sensors.iter().map(|sensor| sensor.address);
+    let configs = join_all(
+        addresses
+            .clone()
+            .map(|address| read_config(client, address)),
+    )
+    .await;
+    addresses
+        .zip(configs)
+        .filter_map(|(address, config)| match config {
+            Ok(Some(config)) => Some(SensorInfo { address, config }),
+            Ok(None) => {
+                eprintln!("Sensor {:#04x} has an invalid hall configuration", address);
+                None
+            }
+            Err(ClientError::Command(_)) => None,
+            Err(err) => {
+                eprintln!(
+                    "Failed to read the configuration of {:#04x}: {}",
+                    address, err
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+async fn read_config<W: AsyncWrite + Unpin>(
+    client: &Client<W>,
+    sensor: u8,
+) -> Result<Option<SensorConfig>, ClientError> {
+    let first = Register::<0x00>::new(client.read_register(sensor, 0x00).await?);
+    let second = Register::<0x01>::new(client.read_register(sensor, 0x01).await?);
+    let third = Register::<0x02>::new(client.read_register(sensor, 0x02).await?);
+    let fourth = Register::<0x03>::new(client.read_register(sensor, 0x03).await?);
+    let tref = Register::<0x24>::new(client.read_register(sensor, 0x24).await?);
+    let Some(hall_configuration) = first.hall_conf() else {
+        return Ok(None);
+    };
+    Ok(Some(SensorConfig {
+        resolution: third.resolution(),
+        gain: first.gain(),
+        temperature_compensation: second.temperature_compensation(),
+        hall_configuration,
+        temp_ref: tref.temperature_reference(),
+        sens_tc: fourth.sens_tc(),
+    }))
+}
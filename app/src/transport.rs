@@ -0,0 +1,357 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::Args;
+use data_transfer::messaging::{encode_frame, MAX_FRAME_SIZE};
+use data_transfer::recording::RecordingReader;
+use futures::{future::BoxFuture, FutureExt};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::time::{sleep_until, Instant};
+use tokio_serial::{SerialPortBuilderExt, SerialPortType};
+
+pub type BoxedReader = Box<dyn AsyncRead + Unpin + Send>;
+pub type BoxedWriter = Box<dyn AsyncWrite + Unpin + Send>;
+
+/// Both directions of an open byte stream to the board.
+pub struct Connection {
+    pub reader: BoxedReader,
+    pub writer: BoxedWriter,
+}
+
+impl Connection {
+    fn new<R, W>(reader: R, writer: W) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        Self {
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+        }
+    }
+}
+
+/// Somewhere frames from the board can be read from.
+pub trait Transport {
+    fn open(&self) -> BoxFuture<'_, io::Result<Connection>>;
+
+    /// Whether anything listens to the commands written to the connection.
+    fn accepts_commands(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum PortSelector {
+    /// The first port found on the system.
+    First,
+    Name(String),
+    Usb {
+        vid: u16,
+        pid: u16,
+    },
+}
+
+pub struct SerialTransport {
+    pub port: PortSelector,
+    pub baud_rate: u32,
+}
+
+impl SerialTransport {
+    fn port_name(&self) -> io::Result<String> {
+        if let PortSelector::Name(name) = &self.port {
+            return Ok(name.clone());
+        }
+        let ports = tokio_serial::available_ports()?;
+        let port = ports
+            .into_iter()
+            .find(|port| match (&self.port, &port.port_type) {
+                (PortSelector::Usb { vid, pid }, SerialPortType::UsbPort(usb)) => {
+                    usb.vid == *vid && usb.pid == *pid
+                }
+                (PortSelector::Usb { .. }, _) => false,
+                _ => true,
+            });
+        port.map(|port| port.port_name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no serial port matching {:?}", self.port),
+            )
+        })
+    }
+}
+
+impl Transport for SerialTransport {
+    fn open(&self) -> BoxFuture<'_, io::Result<Connection>> {
+        async move {
+            let port = tokio_serial::new(self.port_name()?, self.baud_rate).open_native_async()?;
+            let (reader, writer) = tokio::io::split(port);
+            Ok(Connection::new(reader, writer))
+        }
+        .boxed()
+    }
+}
+
+pub struct TcpTransport {
+    pub address: String,
+}
+
+impl Transport for TcpTransport {
+    fn open(&self) -> BoxFuture<'_, io::Result<Connection>> {
+        async move {
+            let stream = tokio::net::TcpStream::connect(&self.address).await?;
+            let (reader, writer) = stream.into_split();
+            Ok(Connection::new(reader, writer))
+        }
+        .boxed()
+    }
+}
+
+#[cfg(unix)]
+pub struct UnixTransport {
+    pub path: PathBuf,
+}
+
+#[cfg(unix)]
+impl Transport for UnixTransport {
+    fn open(&self) -> BoxFuture<'_, io::Result<Connection>> {
+        async move {
+            let stream = tokio::net::UnixStream::connect(&self.path).await?;
+            let (reader, writer) = stream.into_split();
+            Ok(Connection::new(reader, writer))
+        }
+        .boxed()
+    }
+}
+
+/// Replays bytes captured from the board, as fast as they can be read.
+pub struct FileTransport {
+    pub path: PathBuf,
+}
+
+impl Transport for FileTransport {
+    fn open(&self) -> BoxFuture<'_, io::Result<Connection>> {
+        async move {
+            let file = tokio::fs::File::open(&self.path).await?;
+            Ok(Connection::new(file, tokio::io::sink()))
+        }
+        .boxed()
+    }
+
+    fn accepts_commands(&self) -> bool {
+        false
+    }
+}
+
+/// Replays a recording by encoding its frames again, so they go through the same
+/// decoding as frames from the board.
+pub struct ReplayTransport {
+    pub path: PathBuf,
+    /// Keep the recorded gaps between frames instead of replaying as fast as possible.
+    pub realtime: bool,
+}
+
+impl Transport for ReplayTransport {
+    fn open(&self) -> BoxFuture<'_, io::Result<Connection>> {
+        async move {
+            let bytes = tokio::fs::read(&self.path).await?;
+            let recording = RecordingReader::new(bytes.as_slice())?;
+            let mut frames = Vec::new();
+            for frame in recording {
+                match frame {
+                    Ok(frame) => frames.push(frame),
+                    Err(err) => {
+                        eprintln!("Recording ends early: {}", err);
+                        break;
+                    }
+                }
+            }
+
+            let (reader, mut writer) = tokio::io::duplex(MAX_FRAME_SIZE);
+            let realtime = self.realtime;
+            tokio::spawn(async move {
+                let start = Instant::now();
+                let mut buffer = [0; MAX_FRAME_SIZE];
+                for frame in frames {
+                    if realtime {
+                        sleep_until(start + Duration::from_micros(frame.received_us)).await;
+                    }
+                    let Ok(bytes) = encode_frame(&frame.envelope, &mut buffer) else {
+                        continue;
+                    };
+                    if writer.write_all(bytes).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            Ok(Connection::new(reader, tokio::io::sink()))
+        }
+        .boxed()
+    }
+
+    fn accepts_commands(&self) -> bool {
+        false
+    }
+}
+
+/// Where to read frames from. Defaults to the first serial port found.
+#[derive(Args, Debug)]
+#[group(skip)]
+pub struct TransportArgs {
+    /// Serial port to open, by name
+    #[arg(long, group = "source")]
+    port: Option<String>,
+    /// Serial port to open, by USB id given as VID:PID in hex
+    #[arg(long, group = "source", value_name = "VID:PID", value_parser = parse_usb_id)]
+    usb: Option<(u16, u16)>,
+    /// Baud rate of the serial port
+    #[arg(long, default_value_t = 115200)]
+    baud: u32,
+    /// Board simulator or bridge listening on HOST:PORT
+    #[arg(long, group = "source", value_name = "HOST:PORT")]
+    tcp: Option<String>,
+    /// Board simulator or bridge listening on a Unix socket
+    #[cfg(unix)]
+    #[arg(long, group = "source", value_name = "PATH")]
+    unix: Option<PathBuf>,
+    /// File of bytes captured from the board
+    #[arg(long, group = "source", value_name = "PATH")]
+    file: Option<PathBuf>,
+    /// Recording made with the record command
+    #[arg(long, group = "source", value_name = "PATH")]
+    replay: Option<PathBuf>,
+    /// Replay as fast as possible instead of at the recorded speed
+    #[arg(long, requires = "replay")]
+    fast: bool,
+}
+
+impl TransportArgs {
+    /// The recording to replay, if any.
+    pub fn replay_path(&self) -> Option<&Path> {
+        self.replay.as_deref()
+    }
+
+    pub fn transport(self) -> Box<dyn Transport> {
+        #[cfg(unix)]
+        if let Some(path) = self.unix {
+            return Box::new(UnixTransport { path });
+        }
+        if let Some(address) = self.tcp {
+            return Box::new(TcpTransport { address });
+        }
+        if let Some(path) = self.file {
+            return Box::new(FileTransport { path });
+        }
+        if let Some(path) = self.replay {
+            let realtime = !self.fast;
+            return Box::new(ReplayTransport { path, realtime });
+        }
+        let port = match (self.port, self.usb) {
+            (Some(name), _) => PortSelector::Name(name),
+            (None, Some((vid, pid))) => PortSelector::Usb { vid, pid },
+            (None, None) => PortSelector::First,
+        };
+        Box::new(SerialTransport {
+            port,
+            baud_rate: self.baud,
+        })
+    }
+}
+
+fn parse_usb_id(id: &str) -> Result<(u16, u16), String> {
+    let (vid, pid) = id
+        .split_once(':')
+        .ok_or_else(|| format!("expected VID:PID, got {}", id))?;
+    let parse = |part: &str| u16::from_str_radix(part, 16).map_err(|err| err.to_string());
+    Ok((parse(vid)?, parse(pid)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use data_transfer::messaging::{Envelope, FrameDecoder};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        transport: TransportArgs,
+    }
+
+    #[test]
+    fn parses_usb_ids() {
+        assert_eq!(parse_usb_id("0483:5740"), Ok((0x0483, 0x5740)));
+        assert_eq!(parse_usb_id("ffff:0"), Ok((0xFFFF, 0)));
+        assert!(parse_usb_id("0483:57g0").is_err());
+        assert!(parse_usb_id("10000:0001").is_err());
+        assert!(parse_usb_id("04835740").is_err());
+        assert!(parse_usb_id(":5740").is_err());
+    }
+
+    #[test]
+    fn accepts_a_single_source() {
+        let cli = Cli::try_parse_from(["app", "--usb", "0483:5740"]).unwrap();
+        assert_eq!(cli.transport.usb, Some((0x0483, 0x5740)));
+        assert!(Cli::try_parse_from(["app", "--usb", "0483"]).is_err());
+        assert!(Cli::try_parse_from(["app", "--port", "COM1", "--tcp", "localhost:1"]).is_err());
+        assert!(Cli::try_parse_from(["app", "--fast"]).is_err());
+        let cli = Cli::try_parse_from(["app", "--replay", "session.rec", "--fast"]).unwrap();
+        assert!(!cli.transport.transport().accepts_commands());
+    }
+
+    /// Sends a frame from the board side of `board` and reads it through `transport`,
+    /// then checks commands written to the connection reach the board.
+    async fn loopback<S>(transport: &dyn Transport, board: impl std::future::Future<Output = S>)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (connection, mut board) = tokio::join!(transport.open(), board);
+        let Connection {
+            mut reader,
+            mut writer,
+        } = connection.unwrap();
+
+        let mut buffer = [0; MAX_FRAME_SIZE];
+        let frame = encode_frame(&Envelope::new(0x0C, 7, 0, 1234u16), &mut buffer).unwrap();
+        board.write_all(frame).await.unwrap();
+        let mut decoder = FrameDecoder::<MAX_FRAME_SIZE>::new();
+        let mut chunk = [0; 64];
+        let envelope = loop {
+            let n = reader.read(&mut chunk).await.unwrap();
+            assert_ne!(n, 0, "connection closed before the frame");
+            if let Some(frame) = decoder.decode::<u16>(&chunk[..n]).next() {
+                break frame.unwrap();
+            }
+        };
+        assert_eq!((envelope.sequence, envelope.payload), (7, 1234));
+
+        writer.write_all(b"command").await.unwrap();
+        let mut received = [0; 7];
+        board.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"command");
+    }
+
+    #[tokio::test]
+    async fn reads_frames_over_tcp() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let transport = TcpTransport {
+            address: listener.local_addr().unwrap().to_string(),
+        };
+        loopback(&transport, async { listener.accept().await.unwrap().0 }).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn reads_frames_over_a_unix_socket() {
+        let path = std::env::temp_dir().join(format!("board-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let transport = UnixTransport { path: path.clone() };
+        loopback(&transport, async { listener.accept().await.unwrap().0 }).await;
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+//! Subtraction of the background field, like the Earth's field and the bias of the
+//! board, measured while no magnet is present.
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::conversions::{FieldVector, MagneticField, MagneticValue};
+
+/// The background field of one sensor, in µT.
+#[derive(Serialize, Deserialize, Clone, Copy, Format, Debug, Default, PartialEq)]
+pub struct Baseline {
+    pub field: FieldVector,
+}
+
+impl Baseline {
+    /// Subtracts the baseline from the axes of `field`.
+    ///
+    /// Axes the baseline was not captured for are left unchanged.
+    pub fn subtract(&self, field: MagneticField) -> MagneticField {
+        let subtract = |value: Option<MagneticValue>, baseline: Option<f64>| {
+            value.map(|value| MagneticValue::uT(value.microtesla() - baseline.unwrap_or(0.0)))
+        };
+        MagneticField {
+            x: subtract(field.x, self.field.x),
+            y: subtract(field.y, self.field.y),
+            z: subtract(field.z, self.field.z),
+            t: field.t,
+        }
+    }
+}
+
+/// Averages the readings of one sensor into a [`Baseline`].
+#[derive(Clone, Copy, Format, Debug, Default)]
+pub struct BaselineCapture {
+    sum: [f64; 3],
+    samples: [u32; 3],
+    readings: u32,
+}
+
+impl BaselineCapture {
+    pub fn add(&mut self, field: &MagneticField) {
+        for (axis, value) in [field.x, field.y, field.z].into_iter().enumerate() {
+            if let Some(value) = value {
+                self.sum[axis] += value.microtesla();
+                self.samples[axis] += 1;
+            }
+        }
+        self.readings += 1;
+    }
+
+    /// How many readings were added, whichever axes they measured.
+    pub fn readings(&self) -> u32 {
+        self.readings
+    }
+
+    /// The average so far. Axes without any reading have no baseline.
+    pub fn baseline(&self) -> Baseline {
+        let average = |axis: usize| match self.samples[axis] {
+            0 => None,
+            samples => Some(self.sum[axis] / samples as f64),
+        };
+        Baseline {
+            field: FieldVector::from_axes(average(0), average(1), average(2)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversions::TempValue;
+
+    fn field(x: Option<f64>, y: Option<f64>, z: Option<f64>) -> MagneticField {
+        MagneticField {
+            x: x.map(MagneticValue::uT),
+            y: y.map(MagneticValue::uT),
+            z: z.map(MagneticValue::uT),
+            t: Some(TempValue::Celsius(25.0)),
+        }
+    }
+
+    #[test]
+    fn averages_every_axis_on_its_own() {
+        let mut capture = BaselineCapture::default();
+        capture.add(&field(Some(10.0), Some(-20.0), None));
+        capture.add(&field(Some(14.0), Some(-22.0), None));
+        capture.add(&field(Some(12.0), None, None));
+        assert_eq!(capture.readings(), 3);
+        assert_eq!(
+            capture.baseline().field,
+            FieldVector::from_axes(Some(12.0), Some(-21.0), None)
+        );
+
+        capture.add(&field(None, None, Some(5.0)));
+        assert_eq!(
+            capture.baseline().field,
+            FieldVector::from_axes(Some(12.0), Some(-21.0), Some(5.0))
+        );
+    }
+
+    #[test]
+    fn subtracts_the_captured_axes() {
+        let baseline = Baseline {
+            field: FieldVector::from_axes(Some(12.0), Some(-21.0), None),
+        };
+        let corrected = baseline.subtract(field(Some(20.0), None, Some(7.0)));
+        assert_eq!(
+            corrected.vector(),
+            FieldVector::from_axes(Some(8.0), None, Some(7.0))
+        );
+        assert_eq!(corrected.t.unwrap().celsius(), 25.0);
+    }
+}
@@ -1,13 +1,14 @@
-use bitmatch::bitmatch;
+use core::fmt;
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
 use defmt::Format;
-use embedded_io;
-use postcard::ser_flavors::{self, Cobs, Slice};
 use serde::{Deserialize, Serialize};
 
 use crate::memory::{
-    Gain, HallConf, Res3D, Resolution, TempOffset, TempRef, TemperatureCompensation,
+    Gain, HallConf, Res3D, Resolution, SensTc, SensorConfig, TempRef, TemperatureCompensation,
 };
 
+#[derive(Serialize, Deserialize, Clone, Copy, Format, Debug, Default)]
 pub struct MagneticBits {
     pub x: Option<[u8; 2]>,
     pub y: Option<[u8; 2]>,
@@ -26,7 +27,7 @@ impl MagneticBits {
     }
 }
 
-#[derive(Clone, Copy, Format)]
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(usize)]
 pub enum Axis {
     X,
@@ -103,29 +104,56 @@ impl SensitivityPerBit {
     }
 }
 
+/// Temperature readings are 35°C at TREF, and change by 45.2 LSB per °C.
+const TEMPERATURE_AT_REFERENCE: f64 = 35.0;
+const LSB_PER_CELSIUS: f64 = 45.2;
+const ZERO_CELSIUS_IN_KELVIN: f64 = 273.15;
+/// Relative change in sensitivity per °C for one LSB of a SENS_TC coefficient,
+/// which is read as a signed byte.
+///
+/// The datasheet describes SENS_TC_LT and SENS_TC_HT in register 0x03 as the
+/// sensitivity drift below and above TREF, but no Melexis document at hand gives the
+/// weight of one LSB. 2^-13 per °C is an assumption, still to be checked against
+/// Melexis or against readings of a board at two known temperatures.
+const SENS_TC_PER_LSB: f64 = 1.0 / 8192.0;
+
+/// Sensitivity at `celsius` relative to the sensitivity at 35°C, where the
+/// coefficients switch from SENS_TC_LT to SENS_TC_HT.
+fn sensitivity_drift(sens_tc: SensTc, celsius: f64) -> f64 {
+    let delta = celsius - TEMPERATURE_AT_REFERENCE;
+    let coefficient = match delta < 0.0 {
+        true => sens_tc.low,
+        false => sens_tc.high,
+    };
+    1.0 + coefficient as i8 as f64 * SENS_TC_PER_LSB * delta
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy, Format, Debug)]
 pub enum TempValue {
     Celsius(f64),
 }
 impl TempValue {
-    fn from_bits(register: &[u8; 2], offset: TempOffset) -> Self {
+    /// Converts a temperature reading using the sensor's reference from register 0x24.
+    pub fn from_bits(register: &[u8; 2], reference: TempRef) -> Self {
         let t = u16::from_be_bytes(*register) as f64;
-        let offset = u16::from_be_bytes(offset.offset) as f64;
+        let reference = reference.value() as f64;
 
-        Self::Celsius(35.0 + (t - offset) / 45.2)
+        Self::Celsius(TEMPERATURE_AT_REFERENCE + (t - reference) / LSB_PER_CELSIUS)
     }
 
-    fn from_option_bits(register: Option<&[u8; 2]>, offset: TempRef) -> Option<Self> {
-        let t = u16::from_be_bytes(*register?) as f64;
-        let offset = u16::from_be_bytes(offset.offset) as f64;
+    pub fn celsius(&self) -> f64 {
+        match self {
+            Self::Celsius(val) => *val,
+        }
+    }
 
-        Some(Self::Celsius(35.0 + (t - offset) / 45.2))
+    pub fn kelvin(&self) -> f64 {
+        self.celsius() + ZERO_CELSIUS_IN_KELVIN
     }
 
+    /// The temperature in °C.
     pub fn value(&self) -> f64 {
-        match self {
-            Self::Celsius(val) => *val,
-        }
+        self.celsius()
     }
 }
 
@@ -138,49 +166,130 @@ pub struct MagneticField {
 }
 
 impl MagneticField {
+    #[allow(clippy::too_many_arguments)]
     pub fn from_bits(
         x: Option<&[u8; 2]>,
         y: Option<&[u8; 2]>,
         z: Option<&[u8; 2]>,
         temp: Option<&[u8; 2]>,
-        temp_offset: TempRef,
+        temp_ref: TempRef,
         temp_comp: TemperatureCompensation,
         gain: Gain,
         resolution: Res3D,
         hallconf: HallConf,
-    ) -> Option<Self> {
-        let x_field = MagneticValue::from_bits(x, temp_comp, gain, resolution.x, hallconf, Axis::X);
-        let y_field = MagneticValue::from_bits(y, temp_comp, gain, resolution.y, hallconf, Axis::Y);
-        let z_field = MagneticValue::from_bits(z, temp_comp, gain, resolution.z, hallconf, Axis::Z);
-        let temp = TempValue::from_option_bits(temp, temp_offset);
-        Some(Self {
-            x: x_field,
-            y: y_field,
-            z: z_field,
-            t: temp,
+    ) -> Result<Self, DecodeError> {
+        let decode = |value: Option<&[u8; 2]>, resolution, axis| {
+            value
+                .map(|value| {
+                    MagneticValue::from_bits(value, temp_comp, gain, resolution, hallconf, axis)
+                })
+                .transpose()
+        };
+        Ok(Self {
+            x: decode(x, resolution.x, Axis::X)?,
+            y: decode(y, resolution.y, Axis::Y)?,
+            z: decode(z, resolution.z, Axis::Z)?,
+            t: temp.map(|temp| TempValue::from_bits(temp, temp_ref)),
         })
     }
 
     pub fn from_mbits(
         mbits: MagneticBits,
-        temp_offset: TempRef,
+        temp_ref: TempRef,
         temp_comp: TemperatureCompensation,
         gain: Gain,
         resolution: Res3D,
         hallconf: HallConf,
-    ) -> Option<Self> {
+    ) -> Result<Self, DecodeError> {
         Self::from_bits(
             mbits.x.as_ref(),
             mbits.y.as_ref(),
             mbits.z.as_ref(),
             mbits.temp.as_ref(),
-            temp_offset,
+            temp_ref,
             temp_comp,
             gain,
             resolution,
             hallconf,
         )
     }
+
+    pub fn from_config(mbits: MagneticBits, config: &SensorConfig) -> Result<Self, DecodeError> {
+        let field = Self::from_mbits(
+            mbits,
+            config.temp_ref,
+            config.temperature_compensation,
+            config.gain,
+            config.resolution,
+            config.hall_configuration,
+        )?;
+        Ok(match config.temperature_compensation {
+            TemperatureCompensation::Enabled => field.compensate_drift(config.sens_tc),
+            TemperatureCompensation::Disabled => field,
+        })
+    }
+
+    pub fn vector(&self) -> FieldVector {
+        let microtesla = |value: Option<MagneticValue>| value.map(|value| value.microtesla());
+        FieldVector::from_axes(microtesla(self.x), microtesla(self.y), microtesla(self.z))
+    }
+
+    /// Applies `calibration` to the axes, keeping the temperature.
+    pub fn calibrate(self, calibration: &Calibration) -> Self {
+        let vector = calibration.apply(self.vector());
+        Self {
+            x: vector.x.map(MagneticValue::uT),
+            y: vector.y.map(MagneticValue::uT),
+            z: vector.z.map(MagneticValue::uT),
+            t: self.t,
+        }
+    }
+
+    /// Divides out the sensitivity drift at the measured temperature.
+    ///
+    /// Fields measured without the temperature are returned unchanged.
+    pub fn compensate_drift(self, sens_tc: SensTc) -> Self {
+        let Some(temp) = self.t else {
+            return self;
+        };
+        let drift = sensitivity_drift(sens_tc, temp.celsius());
+        let compensate = |value: Option<MagneticValue>| {
+            value.map(|MagneticValue::uT(value)| MagneticValue::uT(value / drift))
+        };
+        Self {
+            x: compensate(self.x),
+            y: compensate(self.y),
+            z: compensate(self.z),
+            t: self.t,
+        }
+    }
+}
+
+/// Why the reading of an axis could not be converted.
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecodeError {
+    /// The counts are outside the range the sensor outputs at this resolution.
+    OutOfRange {
+        axis: Axis,
+        resolution: Resolution,
+        counts: i32,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::OutOfRange {
+                axis,
+                resolution,
+                counts,
+            } => write!(
+                f,
+                "{:?} counts {} are out of range at resolution {:?}",
+                axis, counts, resolution
+            ),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -192,36 +301,767 @@ pub enum Error {
 
 #[derive(Serialize, Deserialize, Clone, Copy, Format, Debug)]
 #[repr(usize)]
+#[allow(non_camel_case_types)]
 pub enum MagneticValue {
     uT(f64),
 }
 
+/// Counts of zero field in the unsigned output formats.
+const OFFSET_BIT16_TO_BIT18: i32 = 1 << 15;
+const OFFSET_BIT19: i32 = 1 << 14;
+/// Largest magnitude, in counts, output at the two highest resolution settings.
+const RANGE_BIT18: i32 = 22000;
+const RANGE_BIT19: i32 = 11000;
+
 impl MagneticValue {
+    /// Converts the reading of one axis, following the output formats of the datasheet.
+    ///
+    /// Without temperature compensation, RES_XYZ 0 and 1 are two's complement, while
+    /// 2 and 3 are unsigned and offset by 2^15 and 2^14. With it, every setting is
+    /// unsigned, offset by 2^15 except for RES_XYZ 3, which keeps its 2^14 offset.
     pub fn from_bits(
-        value: Option<&[u8; 2]>,
+        value: &[u8; 2],
         temp_comp: TemperatureCompensation,
         gain: Gain,
         resolution: Resolution,
         hallconf: HallConf,
         axis: Axis,
-    ) -> Option<Self> {
-        let flip_msb = match (temp_comp, resolution) {
-            (TemperatureCompensation::Disabled, Resolution::BIT19)
-            | (TemperatureCompensation::Disabled, Resolution::BIT18) => Some(1),
-            (TemperatureCompensation::Enabled, Resolution::BIT19 | Resolution::BIT18)
-            | (TemperatureCompensation::Disabled, Resolution::BIT17 | Resolution::BIT16) => {
-                Some(-1)
+    ) -> Result<Self, DecodeError> {
+        let unsigned = i32::from(u16::from_be_bytes(*value));
+        let (counts, range) = match (temp_comp, resolution) {
+            (TemperatureCompensation::Disabled, Resolution::BIT16 | Resolution::BIT17) => {
+                (i32::from(i16::from_be_bytes(*value)), None)
+            }
+            (TemperatureCompensation::Enabled, Resolution::BIT16 | Resolution::BIT17) => {
+                (unsigned - OFFSET_BIT16_TO_BIT18, None)
             }
-            _ => None,
-        }?;
-        let value_to_i16 = i16::from_be_bytes(*value?) * flip_msb;
+            (_, Resolution::BIT18) => (unsigned - OFFSET_BIT16_TO_BIT18, Some(RANGE_BIT18)),
+            (_, Resolution::BIT19) => (unsigned - OFFSET_BIT19, Some(RANGE_BIT19)),
+        };
+        if range.is_some_and(|range| counts.abs() > range) {
+            return Err(DecodeError::OutOfRange {
+                axis,
+                resolution,
+                counts,
+            });
+        }
         let sensitivity = SensitivityPerBit::new(axis, gain, resolution, hallconf);
 
-        Some(Self::uT(sensitivity.value * f64::from(value_to_i16)))
+        Ok(Self::uT(sensitivity.value * f64::from(counts)))
     }
     pub fn value(&self) -> f64 {
+        self.microtesla()
+    }
+
+    pub fn microtesla(&self) -> f64 {
         match self {
             MagneticValue::uT(val) => *val,
         }
     }
+
+    pub fn millitesla(&self) -> f64 {
+        self.microtesla() / MICROTESLA_PER_MILLITESLA
+    }
+
+    pub fn nanotesla(&self) -> f64 {
+        self.microtesla() * NANOTESLA_PER_MICROTESLA
+    }
+
+    pub fn gauss(&self) -> f64 {
+        self.microtesla() / MICROTESLA_PER_GAUSS
+    }
+}
+
+const MICROTESLA_PER_MILLITESLA: f64 = 1000.0;
+const NANOTESLA_PER_MICROTESLA: f64 = 1000.0;
+const MICROTESLA_PER_GAUSS: f64 = 100.0;
+
+/// A magnetic field in µT, where axes the sensor did not measure are `None`.
+///
+/// Arithmetic is done per axis, so an axis missing from either operand is missing
+/// from the result. Results that need every axis, like the norm, are `None` unless
+/// the vectors are complete.
+#[derive(Serialize, Deserialize, Clone, Copy, Format, Debug, Default, PartialEq)]
+pub struct FieldVector {
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    pub z: Option<f64>,
+}
+
+impl FieldVector {
+    /// A vector with every axis measured, in µT.
+    pub const fn new(x: f64, y: f64, z: f64) -> Self {
+        Self {
+            x: Some(x),
+            y: Some(y),
+            z: Some(z),
+        }
+    }
+
+    pub const fn from_axes(x: Option<f64>, y: Option<f64>, z: Option<f64>) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn get(&self, axis: Axis) -> Option<MagneticValue> {
+        let value = match axis {
+            Axis::X => self.x,
+            Axis::Y => self.y,
+            Axis::Z => self.z,
+        };
+        value.map(MagneticValue::uT)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.missing().next().is_none()
+    }
+
+    /// The axes that were not measured.
+    pub fn missing(&self) -> impl Iterator<Item = Axis> + '_ {
+        [Axis::X, Axis::Y, Axis::Z]
+            .into_iter()
+            .filter(|axis| self.get(*axis).is_none())
+    }
+
+    /// Every axis in µT, if none is missing.
+    pub fn microtesla(&self) -> Option<[f64; 3]> {
+        Some([self.x?, self.y?, self.z?])
+    }
+
+    pub fn millitesla(&self) -> Option<[f64; 3]> {
+        self.in_unit(MagneticValue::millitesla)
+    }
+
+    pub fn nanotesla(&self) -> Option<[f64; 3]> {
+        self.in_unit(MagneticValue::nanotesla)
+    }
+
+    pub fn gauss(&self) -> Option<[f64; 3]> {
+        self.in_unit(MagneticValue::gauss)
+    }
+
+    fn in_unit(&self, unit: fn(&MagneticValue) -> f64) -> Option<[f64; 3]> {
+        self.microtesla()
+            .map(|axes| axes.map(|value| unit(&MagneticValue::uT(value))))
+    }
+
+    pub fn norm(&self) -> Option<MagneticValue> {
+        self.dot(self)
+            .map(|squared| MagneticValue::uT(libm::sqrt(squared)))
+    }
+
+    /// The dot product, in µT².
+    pub fn dot(&self, other: &Self) -> Option<f64> {
+        Some(self.x? * other.x? + self.y? * other.y? + self.z? * other.z?)
+    }
+
+    /// The cross product, in µT². Each axis only needs the two other axes of both vectors.
+    pub fn cross(&self, other: &Self) -> Self {
+        let product = |a1: Option<f64>, a2: Option<f64>, b1: Option<f64>, b2: Option<f64>| {
+            Some(a1? * b2? - a2? * b1?)
+        };
+        Self {
+            x: product(self.y, self.z, other.y, other.z),
+            y: product(self.z, self.x, other.z, other.x),
+            z: product(self.x, self.y, other.x, other.y),
+        }
+    }
+
+    fn zip(self, other: Self, op: impl Fn(f64, f64) -> f64) -> Self {
+        let zip = |a: Option<f64>, b: Option<f64>| Some(op(a?, b?));
+        Self {
+            x: zip(self.x, other.x),
+            y: zip(self.y, other.y),
+            z: zip(self.z, other.z),
+        }
+    }
+
+    fn map(self, op: impl Fn(f64) -> f64) -> Self {
+        Self {
+            x: self.x.map(&op),
+            y: self.y.map(&op),
+            z: self.z.map(&op),
+        }
+    }
+}
+
+/// Hard and soft iron correction of one sensor, applied as `matrix · (field − offset)`.
+///
+/// The offset removes constant fields, like the bias of the sensor and of nearby
+/// traces. The matrix corrects the gain mismatch between the axes and the
+/// distortion from soft magnetic materials around the sensor.
+#[derive(Serialize, Deserialize, Clone, Copy, Format, Debug, PartialEq)]
+pub struct Calibration {
+    /// In µT.
+    pub offset: [f64; 3],
+    pub matrix: [[f64; 3]; 3],
+}
+
+impl Calibration {
+    pub const IDENTITY: Self = Self {
+        offset: [0.0; 3],
+        matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+    };
+
+    /// Corrects `vector`. A corrected axis is missing when any axis it is mixed
+    /// from is missing.
+    pub fn apply(&self, vector: FieldVector) -> FieldVector {
+        let centered = [
+            vector.x.map(|x| x - self.offset[0]),
+            vector.y.map(|y| y - self.offset[1]),
+            vector.z.map(|z| z - self.offset[2]),
+        ];
+        let corrected = self.matrix.map(|row| {
+            row.iter()
+                .zip(centered)
+                .filter(|(weight, _)| **weight != 0.0)
+                .map(|(weight, value)| Some(weight * value?))
+                .sum()
+        });
+        FieldVector::from_axes(corrected[0], corrected[1], corrected[2])
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl From<MagneticField> for FieldVector {
+    fn from(field: MagneticField) -> Self {
+        field.vector()
+    }
+}
+
+impl Add for FieldVector {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.zip(other, |a, b| a + b)
+    }
+}
+
+impl Sub for FieldVector {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.zip(other, |a, b| a - b)
+    }
+}
+
+impl Neg for FieldVector {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        self.map(|value| -value)
+    }
+}
+
+impl Mul<f64> for FieldVector {
+    type Output = Self;
+
+    fn mul(self, factor: f64) -> Self {
+        self.map(|value| value * factor)
+    }
+}
+
+impl Div<f64> for FieldVector {
+    type Output = Self;
+
+    fn div(self, divisor: f64) -> Self {
+        self.map(|value| value / divisor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPICAL_TREF: u16 = 46244;
+
+    fn celsius(reading: u16, reference: u16) -> f64 {
+        TempValue::from_bits(
+            &reading.to_be_bytes(),
+            TempRef::from_u8_slice(&reference.to_be_bytes()),
+        )
+        .celsius()
+    }
+
+    #[test]
+    fn temperature_follows_the_datasheet_scale() {
+        // (reading, TREF, °C): 35°C at TREF, 45.2 LSB/°C.
+        let table = [
+            (TYPICAL_TREF, TYPICAL_TREF, 35.0),
+            (TYPICAL_TREF + 452, TYPICAL_TREF, 45.0),
+            (TYPICAL_TREF - 452, TYPICAL_TREF, 25.0),
+            (TYPICAL_TREF - 1582, TYPICAL_TREF, 0.0),
+            (TYPICAL_TREF - 2712, TYPICAL_TREF, -25.0),
+            (TYPICAL_TREF + 2260, TYPICAL_TREF, 85.0),
+            (TYPICAL_TREF + 4068, TYPICAL_TREF, 125.0),
+            (45000, 45000, 35.0),
+            (45452, 45000, 45.0),
+        ];
+        for (reading, reference, expected) in table {
+            let actual = celsius(reading, reference);
+            assert!(
+                (actual - expected).abs() < 1e-9,
+                "{} with TREF {}: {} != {}",
+                reading,
+                reference,
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn kelvin_is_offset_from_celsius() {
+        for (celsius, kelvin) in [(-273.15, 0.0), (0.0, 273.15), (35.0, 308.15)] {
+            assert!((TempValue::Celsius(celsius).kelvin() - kelvin).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn typical_reference_matches_the_datasheet() {
+        assert_eq!(TempRef::TYPICAL.value(), TYPICAL_TREF);
+    }
+
+    #[test]
+    fn field_uses_the_temperature_reference() {
+        let bits = MagneticBits::new(None, None, None, Some((TYPICAL_TREF + 452).to_be_bytes()));
+        let field = MagneticField::from_mbits(
+            bits,
+            TempRef::TYPICAL,
+            TemperatureCompensation::Disabled,
+            Gain::SEVEN,
+            Res3D {
+                x: Resolution::BIT16,
+                y: Resolution::BIT16,
+                z: Resolution::BIT16,
+            },
+            HallConf::FOURPHASE,
+        )
+        .unwrap();
+        assert!((field.t.unwrap().celsius() - 45.0).abs() < 1e-9);
+    }
+
+    fn field(celsius: f64) -> MagneticField {
+        MagneticField {
+            x: Some(MagneticValue::uT(100.0)),
+            y: Some(MagneticValue::uT(-50.0)),
+            z: None,
+            t: Some(TempValue::Celsius(celsius)),
+        }
+    }
+
+    fn assert_close(actual: Option<MagneticValue>, expected: f64) {
+        let actual = actual.unwrap().value();
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn drift_compensation_switches_coefficients_at_35_celsius() {
+        // SENS_TC_LT = 16 and SENS_TC_HT = -32 (0xE0). Worked out by hand for a
+        // reading of 100 µT and -50 µT: at 45°C the sensitivity is
+        // 1 - 32 × 10 / 8192 = 0.9609375 of the one at 35°C, so 100 µT was
+        // 100 / 0.9609375 = 104.065 µT. At 25°C it is 1 - 16 × 10 / 8192 = 0.98046875.
+        let sens_tc = SensTc {
+            low: 16,
+            high: 0xE0,
+        };
+        // (°C, corrected x, corrected y)
+        let table = [
+            (35.0, 100.0, -50.0),
+            (45.0, 104.0650406504065, -52.03252032520325),
+            (85.0, 124.27184466019418, -62.13592233009709),
+            (25.0, 101.99203187250995, -50.99601593625498),
+            (-15.0, 110.82251082251082, -55.41125541125541),
+        ];
+        for (celsius, x, y) in table {
+            let compensated = field(celsius).compensate_drift(sens_tc);
+            assert_close(compensated.x, x);
+            assert_close(compensated.y, y);
+            assert!(compensated.z.is_none());
+        }
+    }
+
+    #[test]
+    fn drift_compensation_needs_a_temperature() {
+        let field = MagneticField {
+            t: None,
+            ..field(35.0)
+        };
+        let compensated = field.compensate_drift(SensTc {
+            low: 0x7F,
+            high: 0x7F,
+        });
+        assert_close(compensated.x, 100.0);
+    }
+
+    #[test]
+    fn drift_is_only_compensated_when_enabled() {
+        let reading = TYPICAL_TREF + 452;
+        let bits = MagneticBits::new(
+            Some(1000i16.to_be_bytes()),
+            None,
+            None,
+            Some(reading.to_be_bytes()),
+        );
+        let mut config = SensorConfig {
+            resolution: Res3D {
+                x: Resolution::BIT16,
+                y: Resolution::BIT16,
+                z: Resolution::BIT16,
+            },
+            gain: Gain::SEVEN,
+            temperature_compensation: TemperatureCompensation::Disabled,
+            hall_configuration: HallConf::FOURPHASE,
+            temp_ref: TempRef::TYPICAL,
+            sens_tc: SensTc { low: 0, high: 0x40 },
+        };
+        let uncompensated = |config: &SensorConfig| {
+            MagneticField::from_mbits(
+                bits,
+                config.temp_ref,
+                config.temperature_compensation,
+                config.gain,
+                config.resolution,
+                config.hall_configuration,
+            )
+            .unwrap()
+            .x
+            .unwrap()
+            .value()
+        };
+
+        let field = MagneticField::from_config(bits, &config).unwrap();
+        assert_close(field.x, uncompensated(&config));
+
+        // 45°C is 10°C above the breakpoint. With SENS_TC_HT = 64 the sensitivity
+        // is 1 + 64 × 10 / 8192 = 1.078125 of the one at 35°C.
+        config.temperature_compensation = TemperatureCompensation::Enabled;
+        let field = MagneticField::from_config(bits, &config).unwrap();
+        assert_close(field.x, uncompensated(&config) / 1.078125);
+    }
+
+    fn decode(
+        raw: u16,
+        temp_comp: TemperatureCompensation,
+        resolution: Resolution,
+    ) -> Result<MagneticValue, DecodeError> {
+        MagneticValue::from_bits(
+            &raw.to_be_bytes(),
+            temp_comp,
+            Gain::SEVEN,
+            resolution,
+            HallConf::FOURPHASE,
+            Axis::X,
+        )
+    }
+
+    fn assert_decodes(
+        temp_comp: TemperatureCompensation,
+        resolution: Resolution,
+        table: &[(u16, i32)],
+    ) {
+        let sensitivity =
+            SensitivityPerBit::new(Axis::X, Gain::SEVEN, resolution, HallConf::FOURPHASE).value;
+        for &(raw, counts) in table {
+            let actual = decode(raw, temp_comp, resolution)
+                .unwrap_or_else(|err| panic!("{:#06x} with {:?}: {:?}", raw, resolution, err));
+            assert!(
+                (actual.value() - sensitivity * counts as f64).abs() < 1e-9,
+                "{:#06x} with {:?} and {:?}: {} != {} counts",
+                raw,
+                resolution,
+                temp_comp,
+                actual.value() / sensitivity,
+                counts
+            );
+        }
+    }
+
+    fn assert_out_of_range(
+        temp_comp: TemperatureCompensation,
+        resolution: Resolution,
+        table: &[(u16, i32)],
+    ) {
+        for &(raw, counts) in table {
+            assert_eq!(
+                decode(raw, temp_comp, resolution).unwrap_err(),
+                DecodeError::OutOfRange {
+                    axis: Axis::X,
+                    resolution,
+                    counts,
+                },
+                "{:#06x} with {:?}",
+                raw,
+                resolution
+            );
+        }
+    }
+
+    const TWOS_COMPLEMENT: [(u16, i32); 5] = [
+        (0x0000, 0),
+        (0x0001, 1),
+        (0xFFFF, -1),
+        (0x7FFF, 32767),
+        (0x8000, -32768),
+    ];
+    const OFFSET_BY_2_15: [(u16, i32); 5] = [
+        (0x8000, 0),
+        (0x8001, 1),
+        (0x7FFF, -1),
+        (0xFFFF, 32767),
+        (0x0000, -32768),
+    ];
+    const OFFSET_BY_2_15_IN_RANGE: [(u16, i32); 5] = [
+        (0x8000, 0),
+        (0x8001, 1),
+        (0x7FFF, -1),
+        (0x8000 + 22000, 22000),
+        (0x8000 - 22000, -22000),
+    ];
+    const OFFSET_BY_2_15_OUT_OF_RANGE: [(u16, i32); 4] = [
+        (0x8000 + 22001, 22001),
+        (0x8000 - 22001, -22001),
+        (0xFFFF, 32767),
+        (0x0000, -32768),
+    ];
+    const OFFSET_BY_2_14_IN_RANGE: [(u16, i32); 5] = [
+        (0x4000, 0),
+        (0x4001, 1),
+        (0x3FFF, -1),
+        (0x4000 + 11000, 11000),
+        (0x4000 - 11000, -11000),
+    ];
+    const OFFSET_BY_2_14_OUT_OF_RANGE: [(u16, i32); 4] = [
+        (0x4000 + 11001, 11001),
+        (0x4000 - 11001, -11001),
+        (0xFFFF, 49151),
+        (0x0000, -16384),
+    ];
+
+    #[test]
+    fn decodes_res_0_without_compensation_as_twos_complement() {
+        assert_decodes(
+            TemperatureCompensation::Disabled,
+            Resolution::BIT16,
+            &TWOS_COMPLEMENT,
+        );
+    }
+
+    #[test]
+    fn decodes_res_1_without_compensation_as_twos_complement() {
+        assert_decodes(
+            TemperatureCompensation::Disabled,
+            Resolution::BIT17,
+            &TWOS_COMPLEMENT,
+        );
+    }
+
+    #[test]
+    fn decodes_res_2_without_compensation_as_offset_by_2_15() {
+        let (temp_comp, resolution) = (TemperatureCompensation::Disabled, Resolution::BIT18);
+        assert_decodes(temp_comp, resolution, &OFFSET_BY_2_15_IN_RANGE);
+        assert_out_of_range(temp_comp, resolution, &OFFSET_BY_2_15_OUT_OF_RANGE);
+    }
+
+    #[test]
+    fn decodes_res_3_without_compensation_as_offset_by_2_14() {
+        let (temp_comp, resolution) = (TemperatureCompensation::Disabled, Resolution::BIT19);
+        assert_decodes(temp_comp, resolution, &OFFSET_BY_2_14_IN_RANGE);
+        assert_out_of_range(temp_comp, resolution, &OFFSET_BY_2_14_OUT_OF_RANGE);
+    }
+
+    #[test]
+    fn decodes_res_0_with_compensation_as_offset_by_2_15() {
+        assert_decodes(
+            TemperatureCompensation::Enabled,
+            Resolution::BIT16,
+            &OFFSET_BY_2_15,
+        );
+    }
+
+    #[test]
+    fn decodes_res_1_with_compensation_as_offset_by_2_15() {
+        assert_decodes(
+            TemperatureCompensation::Enabled,
+            Resolution::BIT17,
+            &OFFSET_BY_2_15,
+        );
+    }
+
+    #[test]
+    fn decodes_res_2_with_compensation_as_offset_by_2_15() {
+        let (temp_comp, resolution) = (TemperatureCompensation::Enabled, Resolution::BIT18);
+        assert_decodes(temp_comp, resolution, &OFFSET_BY_2_15_IN_RANGE);
+        assert_out_of_range(temp_comp, resolution, &OFFSET_BY_2_15_OUT_OF_RANGE);
+    }
+
+    #[test]
+    fn decodes_res_3_with_compensation_as_offset_by_2_14() {
+        let (temp_comp, resolution) = (TemperatureCompensation::Enabled, Resolution::BIT19);
+        assert_decodes(temp_comp, resolution, &OFFSET_BY_2_14_IN_RANGE);
+        assert_out_of_range(temp_comp, resolution, &OFFSET_BY_2_14_OUT_OF_RANGE);
+    }
+
+    #[test]
+    fn field_reports_the_axis_out_of_range() {
+        let bits = MagneticBits::new(Some(0x8000u16.to_be_bytes()), None, Some([0, 0]), None);
+        let resolution = Res3D {
+            x: Resolution::BIT18,
+            y: Resolution::BIT18,
+            z: Resolution::BIT19,
+        };
+        let error = MagneticField::from_mbits(
+            bits,
+            TempRef::TYPICAL,
+            TemperatureCompensation::Disabled,
+            Gain::SEVEN,
+            resolution,
+            HallConf::FOURPHASE,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            DecodeError::OutOfRange {
+                axis: Axis::Z,
+                resolution: Resolution::BIT19,
+                counts: -16384,
+            }
+        );
+    }
+
+    fn assert_vector(actual: FieldVector, expected: FieldVector) {
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            match (actual.get(axis), expected.get(axis)) {
+                (Some(actual), Some(expected)) => {
+                    assert!(
+                        (actual.value() - expected.value()).abs() < 1e-9,
+                        "{:?}: {:?} != {:?}",
+                        axis,
+                        actual,
+                        expected
+                    )
+                }
+                (actual, expected) => assert_eq!(
+                    actual.is_some(),
+                    expected.is_some(),
+                    "{:?}: {:?} != {:?}",
+                    axis,
+                    actual,
+                    expected
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn converts_between_units() {
+        let value = MagneticValue::uT(50.0);
+        assert!((value.millitesla() - 0.05).abs() < 1e-12);
+        assert!((value.nanotesla() - 50_000.0).abs() < 1e-9);
+        assert!((value.gauss() - 0.5).abs() < 1e-12);
+
+        let vector = FieldVector::new(100.0, -200.0, 0.0);
+        assert_eq!(vector.gauss(), Some([1.0, -2.0, 0.0]));
+        assert_eq!(vector.millitesla(), Some([0.1, -0.2, 0.0]));
+        assert_eq!(vector.nanotesla(), Some([100_000.0, -200_000.0, 0.0]));
+    }
+
+    #[test]
+    fn vector_ops_follow_the_axes() {
+        let a = FieldVector::new(1.0, 2.0, 3.0);
+        let b = FieldVector::new(4.0, -5.0, 6.0);
+        assert_vector(a + b, FieldVector::new(5.0, -3.0, 9.0));
+        assert_vector(a - b, FieldVector::new(-3.0, 7.0, -3.0));
+        assert_vector(-a, FieldVector::new(-1.0, -2.0, -3.0));
+        assert_vector(a * 2.0, FieldVector::new(2.0, 4.0, 6.0));
+        assert_vector(b / 2.0, FieldVector::new(2.0, -2.5, 3.0));
+        assert_eq!(a.dot(&b), Some(12.0));
+        assert_vector(a.cross(&b), FieldVector::new(27.0, 6.0, -13.0));
+        assert_eq!(a.cross(&b).dot(&a), Some(0.0));
+        assert!((FieldVector::new(3.0, 4.0, 12.0).norm().unwrap().value() - 13.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn missing_axes_stay_missing() {
+        let partial = FieldVector::from_axes(Some(1.0), None, Some(3.0));
+        let complete = FieldVector::new(4.0, 5.0, 6.0);
+        assert!(!partial.is_complete());
+        assert!(complete.is_complete());
+        assert!(partial.missing().eq([Axis::Y]));
+
+        assert_vector(
+            partial + complete,
+            FieldVector::from_axes(Some(5.0), None, Some(9.0)),
+        );
+        assert_vector(
+            -partial,
+            FieldVector::from_axes(Some(-1.0), None, Some(-3.0)),
+        );
+        // X and Z of the cross product both need Y.
+        assert_vector(
+            partial.cross(&complete),
+            FieldVector::from_axes(None, Some(6.0), None),
+        );
+        assert_eq!(partial.dot(&complete), None);
+        assert!(partial.norm().is_none());
+        assert_eq!(partial.microtesla(), None);
+    }
+
+    #[test]
+    fn field_vector_keeps_missing_axes() {
+        let vector = field(35.0).vector();
+        assert_vector(
+            vector,
+            FieldVector::from_axes(Some(100.0), Some(-50.0), None),
+        );
+        assert_eq!(
+            FieldVector::from(MagneticField::default()),
+            FieldVector::default()
+        );
+    }
+
+    #[test]
+    fn calibration_removes_the_offset_then_corrects() {
+        let calibration = Calibration {
+            offset: [10.0, -20.0, 5.0],
+            matrix: [[2.0, 0.0, 0.0], [0.0, 0.5, 0.0], [1.0, 0.0, 1.0]],
+        };
+        assert_vector(
+            calibration.apply(FieldVector::new(11.0, -18.0, 8.0)),
+            FieldVector::new(2.0, 1.0, 4.0),
+        );
+        assert_vector(
+            Calibration::IDENTITY.apply(FieldVector::new(1.0, 2.0, 3.0)),
+            FieldVector::new(1.0, 2.0, 3.0),
+        );
+    }
+
+    #[test]
+    fn calibration_only_needs_the_axes_it_mixes() {
+        let calibration = Calibration {
+            offset: [1.0, 1.0, 1.0],
+            matrix: [[2.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 1.0]],
+        };
+        let corrected = calibration.apply(FieldVector::from_axes(None, Some(3.0), Some(4.0)));
+        assert_vector(corrected, FieldVector::from_axes(None, Some(2.0), None));
+
+        let field = MagneticField {
+            x: None,
+            y: Some(MagneticValue::uT(3.0)),
+            z: Some(MagneticValue::uT(4.0)),
+            t: Some(TempValue::Celsius(20.0)),
+        }
+        .calibrate(&calibration);
+        assert_vector(field.vector(), corrected);
+        assert_eq!(field.t.unwrap().celsius(), 20.0);
+    }
 }
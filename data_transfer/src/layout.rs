@@ -0,0 +1,310 @@
+//! Where the sensors sit on the board and how they are wired.
+//!
+//! The firmware uses [`BOARD_LAYOUT`] directly. The host can load a layout from a
+//! file, and checks it describes the same board by comparing [`BoardLayout::hash`]
+//! with the hash the board sends in its handshake.
+use core::fmt;
+
+use defmt::Format;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::conversions::{FieldVector, MagneticField, MagneticValue};
+use crate::messaging::CRC;
+
+/// Label of a GPIO pin, like `PB14`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinLabel {
+    /// `b'A'` for port A, and so on.
+    pub port: u8,
+    pub pin: u8,
+}
+
+impl PinLabel {
+    pub const fn new(port: char, pin: u8) -> Self {
+        Self {
+            port: port as u8,
+            pin,
+        }
+    }
+
+    pub fn parse(label: &str) -> Option<Self> {
+        let label = label.strip_prefix('P')?;
+        let port = *label.as_bytes().first()?;
+        if !port.is_ascii_uppercase() {
+            return None;
+        }
+        let pin = label[1..].parse().ok()?;
+        Some(Self { port, pin })
+    }
+}
+
+impl fmt::Display for PinLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "P{}{}", self.port as char, self.pin)
+    }
+}
+
+impl Format for PinLabel {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "P{}{}", self.port as char, self.pin)
+    }
+}
+
+impl Serialize for PinLabel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PinLabel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl de::Visitor<'_> for Visitor {
+            type Value = PinLabel;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a pin label like PB14")
+            }
+
+            fn visit_str<E: de::Error>(self, label: &str) -> Result<PinLabel, E> {
+                PinLabel::parse(label)
+                    .ok_or_else(|| E::invalid_value(de::Unexpected::Str(label), &self))
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+/// A direction along one of the board axes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Format)]
+pub enum BoardAxis {
+    #[serde(rename = "+x")]
+    PlusX,
+    #[serde(rename = "-x")]
+    MinusX,
+    #[serde(rename = "+y")]
+    PlusY,
+    #[serde(rename = "-y")]
+    MinusY,
+    #[serde(rename = "+z")]
+    PlusZ,
+    #[serde(rename = "-z")]
+    MinusZ,
+}
+
+impl BoardAxis {
+    /// Index of the board axis, and whether the direction is reversed.
+    fn index(self) -> (usize, bool) {
+        match self {
+            BoardAxis::PlusX => (0, false),
+            BoardAxis::MinusX => (0, true),
+            BoardAxis::PlusY => (1, false),
+            BoardAxis::MinusY => (1, true),
+            BoardAxis::PlusZ => (2, false),
+            BoardAxis::MinusZ => (2, true),
+        }
+    }
+}
+
+/// The board direction each axis of a sensor points to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Format)]
+pub struct Orientation {
+    pub x: BoardAxis,
+    pub y: BoardAxis,
+    pub z: BoardAxis,
+}
+
+impl Orientation {
+    /// Sensor axes along the board axes.
+    pub const ALIGNED: Self = Self {
+        x: BoardAxis::PlusX,
+        y: BoardAxis::PlusY,
+        z: BoardAxis::PlusZ,
+    };
+
+    /// Whether every sensor axis maps onto a different board axis.
+    pub fn is_valid(&self) -> bool {
+        let [x, y, z] = [self.x, self.y, self.z].map(|axis| axis.index().0);
+        x != y && y != z && x != z
+    }
+
+    /// Expresses a field measured along the sensor axes along the board axes.
+    pub fn to_board(&self, vector: FieldVector) -> FieldVector {
+        let mut board = [None; 3];
+        for (axis, value) in [(self.x, vector.x), (self.y, vector.y), (self.z, vector.z)] {
+            let (index, reversed) = axis.index();
+            board[index] = value.map(|value| if reversed { -value } else { value });
+        }
+        FieldVector::from_axes(board[0], board[1], board[2])
+    }
+
+    /// [`Orientation::to_board`] for the axes of a reading, keeping its temperature.
+    pub fn field_to_board(&self, field: MagneticField) -> MagneticField {
+        let vector = self.to_board(field.vector());
+        MagneticField {
+            x: vector.x.map(MagneticValue::uT),
+            y: vector.y.map(MagneticValue::uT),
+            z: vector.z.map(MagneticValue::uT),
+            t: field.t,
+        }
+    }
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Self::ALIGNED
+    }
+}
+
+/// One sensor of the board.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Format)]
+pub struct SensorPlacement {
+    /// I2C address.
+    pub address: u8,
+    /// Pin the INT line of the sensor is wired to.
+    pub interrupt: PinLabel,
+    /// In mm from the center of the board.
+    pub position: [f32; 3],
+    #[serde(default)]
+    pub orientation: Orientation,
+}
+
+impl SensorPlacement {
+    pub const fn new(address: u8, interrupt: PinLabel, position: [f32; 3]) -> Self {
+        Self {
+            address,
+            interrupt,
+            position,
+            orientation: Orientation::ALIGNED,
+        }
+    }
+}
+
+/// The sensors of a board, in the order of the indices of a
+/// [`BoardFrame`](crate::messaging::BoardFrame).
+#[derive(Clone, Copy, Debug)]
+pub struct BoardLayout<'a> {
+    pub sensors: &'a [SensorPlacement],
+}
+
+impl<'a> BoardLayout<'a> {
+    pub const fn new(sensors: &'a [SensorPlacement]) -> Self {
+        Self { sensors }
+    }
+
+    pub fn sensor(&self, address: u8) -> Option<&'a SensorPlacement> {
+        self.sensors.iter().find(|sensor| sensor.address == address)
+    }
+
+    /// CRC-32 of everything in the layout, in order.
+    ///
+    /// Computed over the fields rather than a serialized form, so it does not
+    /// depend on how the layout was stored.
+    pub fn hash(&self) -> u32 {
+        let mut digest = CRC.digest();
+        for sensor in self.sensors {
+            digest.update(&[sensor.address, sensor.interrupt.port, sensor.interrupt.pin]);
+            for coordinate in sensor.position {
+                digest.update(&coordinate.to_le_bytes());
+            }
+            let orientation = sensor.orientation;
+            digest.update(&[orientation.x, orientation.y, orientation.z].map(|axis| axis as u8));
+        }
+        digest.finalize()
+    }
+}
+
+/// The 4×4 grid of the board, on a 4.5 mm pitch.
+///
+/// The pins follow the wiring notes this table replaces, which list several
+/// sensors on PA10.
+pub const BOARD_LAYOUT: BoardLayout<'static> = BoardLayout::new(&[
+    SensorPlacement::new(0x0C, PinLabel::new('B', 0), [6.75, -6.75, 0.0]),
+    SensorPlacement::new(0x0D, PinLabel::new('B', 14), [6.75, -2.25, 0.0]),
+    SensorPlacement::new(0x0E, PinLabel::new('B', 13), [6.75, 2.25, 0.0]),
+    SensorPlacement::new(0x0F, PinLabel::new('A', 10), [6.75, 6.75, 0.0]),
+    SensorPlacement::new(0x10, PinLabel::new('B', 4), [2.25, -6.75, 0.0]),
+    SensorPlacement::new(0x11, PinLabel::new('B', 3), [2.25, -2.25, 0.0]),
+    SensorPlacement::new(0x12, PinLabel::new('A', 10), [2.25, 2.25, 0.0]),
+    SensorPlacement::new(0x13, PinLabel::new('A', 10), [2.25, 6.75, 0.0]),
+    SensorPlacement::new(0x14, PinLabel::new('A', 12), [-2.25, -6.75, 0.0]),
+    SensorPlacement::new(0x15, PinLabel::new('B', 5), [-2.25, -2.25, 0.0]),
+    SensorPlacement::new(0x16, PinLabel::new('A', 10), [-2.25, 2.25, 0.0]),
+    SensorPlacement::new(0x17, PinLabel::new('A', 10), [-2.25, 6.75, 0.0]),
+    SensorPlacement::new(0x18, PinLabel::new('A', 9), [-6.75, -6.75, 0.0]),
+    SensorPlacement::new(0x19, PinLabel::new('A', 2), [-6.75, -2.25, 0.0]),
+    SensorPlacement::new(0x1A, PinLabel::new('A', 10), [-6.75, 2.25, 0.0]),
+    SensorPlacement::new(0x1B, PinLabel::new('A', 10), [-6.75, 6.75, 0.0]),
+]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messaging::{BOARD_SENSORS, FIRST_SENSOR_ADDRESS};
+
+    #[test]
+    fn board_layout_covers_every_address() {
+        assert_eq!(BOARD_LAYOUT.sensors.len(), BOARD_SENSORS);
+        for (index, sensor) in BOARD_LAYOUT.sensors.iter().enumerate() {
+            assert_eq!(sensor.address, FIRST_SENSOR_ADDRESS + index as u8);
+            assert!(sensor.orientation.is_valid());
+        }
+        assert_eq!(
+            BOARD_LAYOUT.sensor(0x0D).unwrap().interrupt,
+            PinLabel::new('B', 14)
+        );
+        assert!(BOARD_LAYOUT.sensor(0x1C).is_none());
+    }
+
+    #[test]
+    fn parses_pin_labels() {
+        assert_eq!(PinLabel::parse("PB14"), Some(PinLabel::new('B', 14)));
+        assert_eq!(PinLabel::parse("PA0"), Some(PinLabel::new('A', 0)));
+        for invalid in ["", "P", "PB", "B14", "Pb14", "PB-1", "PB1x"] {
+            assert_eq!(PinLabel::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn hash_changes_with_the_geometry() {
+        let mut sensors = [BOARD_LAYOUT.sensors[0], BOARD_LAYOUT.sensors[1]];
+        let hash = BoardLayout::new(&sensors).hash();
+        assert_eq!(BoardLayout::new(&sensors).hash(), hash);
+
+        sensors[1].position[2] = 0.5;
+        let moved = BoardLayout::new(&sensors).hash();
+        assert_ne!(moved, hash);
+
+        sensors[1].orientation.z = BoardAxis::MinusZ;
+        assert_ne!(BoardLayout::new(&sensors).hash(), moved);
+
+        sensors.swap(0, 1);
+        assert_ne!(BoardLayout::new(&sensors[..1]).hash(), hash);
+    }
+
+    #[test]
+    fn orientation_maps_sensor_axes_onto_the_board() {
+        let flipped = Orientation {
+            x: BoardAxis::PlusY,
+            y: BoardAxis::MinusX,
+            z: BoardAxis::MinusZ,
+        };
+        assert!(flipped.is_valid());
+        assert_eq!(
+            flipped.to_board(FieldVector::from_axes(Some(1.0), Some(2.0), None)),
+            FieldVector::from_axes(Some(-2.0), Some(1.0), None)
+        );
+        assert_eq!(
+            Orientation::ALIGNED.to_board(FieldVector::new(1.0, 2.0, 3.0)),
+            FieldVector::new(1.0, 2.0, 3.0)
+        );
+        assert!(!Orientation {
+            x: BoardAxis::PlusX,
+            y: BoardAxis::MinusX,
+            z: BoardAxis::PlusZ,
+        }
+        .is_valid());
+    }
+}
@@ -1,4 +1,8 @@
 #![no_std]
+pub mod baseline;
 pub mod conversions;
+pub mod layout;
 pub mod memory;
 pub mod messaging;
+#[cfg(feature = "use-std")]
+pub mod recording;
@@ -1,7 +1,12 @@
-use bitflags::{bitflags, Flags};
 use bitmatch::bitmatch;
 use defmt::Format;
+use serde::{Deserialize, Serialize};
 
+/// The contents of the register at address `R`.
+///
+/// Fields are read and written through their [`CustomerMemoryArea`], so setting one
+/// field keeps every other bit of the word.
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq)]
 pub struct Register<const R: u8> {
     data: [u8; 2],
 }
@@ -10,36 +15,72 @@ impl<const R: u8> Register<R> {
     pub fn new(data: [u8; 2]) -> Self {
         Self { data }
     }
+
+    pub fn data(&self) -> [u8; 2] {
+        self.data
+    }
+
+    fn field(&self, area: CustomerMemoryArea) -> u16 {
+        debug_assert_eq!(area.register(), R);
+        area.read(self.data)
+    }
+
+    fn set_field(&mut self, area: CustomerMemoryArea, value: u16) -> &mut Self {
+        debug_assert_eq!(area.register(), R);
+        self.data = area.write(self.data, value);
+        self
+    }
 }
 
 impl Register<0x00> {
-    fn flags(&self) -> RegisterOneFlags {
-        RegisterOneFlags::from_bits_retain(u16::from_be_bytes(self.data))
+    pub fn zseries(&self) -> ZSeries {
+        ZSeries::from_u8_slice(&self.data)
     }
 
-    pub fn zseries(&self) -> ZSeries {
-        match self.flags().contains(RegisterOneFlags::ZSeries) {
-            true => ZSeries::Enabled,
-            false => ZSeries::Disabled,
-        }
+    pub fn set_zseries(&mut self, zseries: ZSeries) -> &mut Self {
+        self.data = zseries.to_u8_slice(&self.data);
+        self
     }
 
     pub fn bist(&self) -> Bist {
-        match self.flags().contains(RegisterOneFlags::Bist) {
-            true => Bist::Enabled,
-            false => Bist::Disabled,
-        }
+        Bist::from_u8_slice(&self.data)
+    }
+
+    pub fn set_bist(&mut self, bist: Bist) -> &mut Self {
+        self.data = bist.to_u8_slice(&self.data);
+        self
     }
 
     pub fn hall_conf(&self) -> Option<HallConf> {
         HallConf::from_u8_slice(&self.data)
     }
 
+    pub fn set_hall_conf(&mut self, hall_conf: HallConf) -> &mut Self {
+        self.data = hall_conf.to_u8_slice(&self.data);
+        self
+    }
+
     pub fn gain(&self) -> Gain {
         Gain::from_u8_slice(&self.data)
     }
+
+    pub fn set_gain(&mut self, gain: Gain) -> &mut Self {
+        self.data = gain.to_u8_slice(&self.data);
+        self
+    }
+
+    /// Reserved for Melexis, 7 bits.
+    pub fn ana_reserved_low(&self) -> u8 {
+        self.field(CustomerMemoryArea::AnaReservedLow) as u8
+    }
+
+    pub fn set_ana_reserved_low(&mut self, value: u8) -> &mut Self {
+        self.set_field(CustomerMemoryArea::AnaReservedLow, value as u16)
+    }
 }
 
+/// A set of axes, as selected by the zyxt bits of the measurement commands.
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq)]
 pub struct BurstSel {
     pub x: bool,
     pub y: bool,
@@ -47,111 +88,300 @@ pub struct BurstSel {
     pub temp: bool,
 }
 
+/// Which interfaces the sensor listens on.
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq)]
+pub enum CommMode {
+    Both,
+    SpiOnly,
+    I2cOnly,
+}
+
+impl CommMode {
+    pub fn from_u8_slice(val: &[u8; 2]) -> Self {
+        match CustomerMemoryArea::CommMode.read(*val) {
+            0b10 => Self::SpiOnly,
+            0b11 => Self::I2cOnly,
+            _ => Self::Both,
+        }
+    }
+
+    /// Writes the mode into the register word `val`, keeping every other bit.
+    pub fn to_u8_slice(self, val: &[u8; 2]) -> [u8; 2] {
+        let value = match self {
+            Self::Both => 0b00,
+            Self::SpiOnly => 0b10,
+            Self::I2cOnly => 0b11,
+        };
+        CustomerMemoryArea::CommMode.write(*val, value)
+    }
+}
+
 impl Register<0x01> {
-    fn flags(&self) -> RegisterTwoFlags {
-        RegisterTwoFlags::from_bits_retain(u16::from_be_bytes(self.data))
+    /// Interval between burst measurements, in steps of 20ms, 6 bits.
+    pub fn burst_data_rate(&self) -> u8 {
+        self.field(CustomerMemoryArea::BurstDataRate) as u8
+    }
+
+    pub fn set_burst_data_rate(&mut self, rate: u8) -> &mut Self {
+        self.set_field(CustomerMemoryArea::BurstDataRate, rate as u16)
     }
 
     pub fn burst_sel(&self) -> BurstSel {
-        let x = self.flags().contains(RegisterTwoFlags::BurstSelX);
-        let y = self.flags().contains(RegisterTwoFlags::BurstSelY);
-        let z = self.flags().contains(RegisterTwoFlags::BurstSelZ);
-        let temp = self.flags().contains(RegisterTwoFlags::BurstSelT);
-        BurstSel { x, y, z, temp }
+        let bits = self.field(CustomerMemoryArea::BurstSel);
+        BurstSel {
+            x: bits & 0b0010 != 0,
+            y: bits & 0b0100 != 0,
+            z: bits & 0b1000 != 0,
+            temp: bits & 0b0001 != 0,
+        }
+    }
+
+    pub fn set_burst_sel(&mut self, burst_sel: BurstSel) -> &mut Self {
+        let bits = (burst_sel.z as u16) << 3
+            | (burst_sel.y as u16) << 2
+            | (burst_sel.x as u16) << 1
+            | burst_sel.temp as u16;
+        self.set_field(CustomerMemoryArea::BurstSel, bits)
     }
 
     pub fn temperature_compensation(&self) -> TemperatureCompensation {
-        match self.flags().contains(RegisterTwoFlags::TcmpEn) {
-            true => TemperatureCompensation::Enabled,
-            false => TemperatureCompensation::Disabled,
-        }
+        TemperatureCompensation::from_u8_slice(&self.data)
+    }
+
+    pub fn set_temperature_compensation(
+        &mut self,
+        compensation: TemperatureCompensation,
+    ) -> &mut Self {
+        self.data = compensation.to_u8_slice(&self.data);
+        self
     }
 
     pub fn external_trigger(&self) -> bool {
-        self.flags().contains(RegisterTwoFlags::ExtTrig)
+        self.field(CustomerMemoryArea::ExtTrg) != 0
+    }
+
+    pub fn set_external_trigger(&mut self, enabled: bool) -> &mut Self {
+        self.set_field(CustomerMemoryArea::ExtTrg, enabled as u16)
     }
 
     pub fn wake_on_change_diff(&self) -> bool {
-        self.flags().contains(RegisterTwoFlags::WOCDiff)
+        self.field(CustomerMemoryArea::WocDiff) != 0
+    }
+
+    pub fn set_wake_on_change_diff(&mut self, enabled: bool) -> &mut Self {
+        self.set_field(CustomerMemoryArea::WocDiff, enabled as u16)
+    }
+
+    pub fn comm_mode(&self) -> CommMode {
+        CommMode::from_u8_slice(&self.data)
+    }
+
+    pub fn set_comm_mode(&mut self, mode: CommMode) -> &mut Self {
+        self.data = mode.to_u8_slice(&self.data);
+        self
     }
 
     pub fn trigger_interrupt(&self) -> bool {
-        self.flags().contains(RegisterTwoFlags::TrigInt)
+        self.field(CustomerMemoryArea::TrigInt) != 0
+    }
+
+    pub fn set_trigger_interrupt(&mut self, enabled: bool) -> &mut Self {
+        self.set_field(CustomerMemoryArea::TrigInt, enabled as u16)
     }
 }
 
 impl Register<0x02> {
+    pub fn oversampling(&self) -> Oversampling {
+        Oversampling::from_index(self.field(CustomerMemoryArea::OSR))
+    }
+
+    pub fn set_oversampling(&mut self, oversampling: Oversampling) -> &mut Self {
+        self.set_field(CustomerMemoryArea::OSR, oversampling as u16)
+    }
+
+    pub fn digital_filter(&self) -> DigitalFilter {
+        DigitalFilter::from_u8_slice(&self.data)
+    }
+
+    pub fn set_digital_filter(&mut self, filter: DigitalFilter) -> &mut Self {
+        self.data = filter.to_u8_slice(&self.data);
+        self
+    }
+
     pub fn resolution(&self) -> Res3D {
         Res3D::from_u8_slice(&self.data)
     }
+
+    pub fn set_resolution(&mut self, resolution: Res3D) -> &mut Self {
+        self.data = resolution.to_u8_slice(&self.data);
+        self
+    }
+
+    pub fn conversion_settings(&self) -> ConversionSettings {
+        ConversionSettings {
+            oversampling: self.oversampling(),
+            temperature_oversampling: self.temperature_oversampling(),
+            digital_filter: self.digital_filter(),
+        }
+    }
+
+    /// Oversampling of the temperature measurement (OSR2).
+    pub fn temperature_oversampling(&self) -> Oversampling {
+        Oversampling::from_index(self.field(CustomerMemoryArea::OSR2))
+    }
+
+    pub fn set_temperature_oversampling(&mut self, oversampling: Oversampling) -> &mut Self {
+        self.set_field(CustomerMemoryArea::OSR2, oversampling as u16)
+    }
 }
 
 impl Register<0x03> {
-    pub fn temperature_offset(&self) -> TempOffset {
-        TempOffset::from_u8_slice(&self.data)
+    pub fn sens_tc(&self) -> SensTc {
+        SensTc {
+            low: self.sens_tc_lt(),
+            high: self.sens_tc_ht(),
+        }
+    }
+
+    /// Sensitivity drift compensation below 35°C.
+    pub fn sens_tc_lt(&self) -> u8 {
+        self.field(CustomerMemoryArea::SensTcLT) as u8
+    }
+
+    pub fn set_sens_tc_lt(&mut self, value: u8) -> &mut Self {
+        self.set_field(CustomerMemoryArea::SensTcLT, value as u16)
+    }
+
+    /// Sensitivity drift compensation above 35°C.
+    pub fn sens_tc_ht(&self) -> u8 {
+        self.field(CustomerMemoryArea::SensTcHT) as u8
+    }
+
+    pub fn set_sens_tc_ht(&mut self, value: u8) -> &mut Self {
+        self.set_field(CustomerMemoryArea::SensTcHT, value as u16)
     }
 }
 
-impl Register<0x24> {
-    pub fn temperature_reference(&self) -> TempRef {
-        TempRef::from_u8_slice(&self.data)
+impl Register<0x04> {
+    pub fn offset_x(&self) -> u16 {
+        self.field(CustomerMemoryArea::OffsetX)
+    }
+
+    pub fn set_offset_x(&mut self, offset: u16) -> &mut Self {
+        self.set_field(CustomerMemoryArea::OffsetX, offset)
     }
 }
 
-#[derive(Clone, Copy, Format)]
-pub struct TempOffset {
-    pub offset: [u8; 2],
+impl Register<0x05> {
+    pub fn offset_y(&self) -> u16 {
+        self.field(CustomerMemoryArea::OffsetY)
+    }
+
+    pub fn set_offset_y(&mut self, offset: u16) -> &mut Self {
+        self.set_field(CustomerMemoryArea::OffsetY, offset)
+    }
 }
-impl TempOffset {
-    pub fn from_u8_slice(offset: &[u8; 2]) -> Self {
-        Self { offset: *offset }
+
+impl Register<0x06> {
+    pub fn offset_z(&self) -> u16 {
+        self.field(CustomerMemoryArea::OffsetZ)
+    }
+
+    pub fn set_offset_z(&mut self, offset: u16) -> &mut Self {
+        self.set_field(CustomerMemoryArea::OffsetZ, offset)
+    }
+}
+
+impl Register<0x07> {
+    /// Wake-on-change threshold for the X and Y axes.
+    pub fn wo_xy_threshold(&self) -> u16 {
+        self.field(CustomerMemoryArea::WOxyThreshold)
+    }
+
+    pub fn set_wo_xy_threshold(&mut self, threshold: u16) -> &mut Self {
+        self.set_field(CustomerMemoryArea::WOxyThreshold, threshold)
     }
 }
 
-#[derive(Clone, Copy, Format)]
+impl Register<0x08> {
+    /// Wake-on-change threshold for the Z axis.
+    pub fn wo_z_threshold(&self) -> u16 {
+        self.field(CustomerMemoryArea::WOzThreshold)
+    }
+
+    pub fn set_wo_z_threshold(&mut self, threshold: u16) -> &mut Self {
+        self.set_field(CustomerMemoryArea::WOzThreshold, threshold)
+    }
+}
+
+impl Register<0x24> {
+    pub fn temperature_reference(&self) -> TempRef {
+        TempRef::from_u8_slice(&self.data)
+    }
+}
+
+/// The temperature reading at 35°C (TREF), trimmed per sensor in register 0x24.
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TempRef {
     pub offset: [u8; 2],
 }
 impl TempRef {
+    /// The datasheet typical value, for sensors whose TREF has not been read.
+    pub const TYPICAL: Self = Self {
+        offset: 46244u16.to_be_bytes(),
+    };
+
     pub fn from_u8_slice(offset: &[u8; 2]) -> Self {
         Self { offset: *offset }
     }
+
+    pub fn value(&self) -> u16 {
+        u16::from_be_bytes(self.offset)
+    }
 }
 
-#[derive(Clone, Copy, Format)]
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq)]
 #[repr(usize)]
 pub enum ZSeries {
     Disabled,
     Enabled,
 }
 
-#[derive(Clone, Copy, Format)]
+impl ZSeries {
+    pub fn from_u8_slice(val: &[u8; 2]) -> Self {
+        match CustomerMemoryArea::ZSeries.read(*val) {
+            0 => Self::Disabled,
+            _ => Self::Enabled,
+        }
+    }
+
+    /// Writes the setting into the register word `val`, keeping every other bit.
+    pub fn to_u8_slice(self, val: &[u8; 2]) -> [u8; 2] {
+        CustomerMemoryArea::ZSeries.write(*val, self as u16)
+    }
+}
+
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq)]
 #[repr(usize)]
 pub enum Bist {
     Disabled,
     Enabled,
 }
-bitflags! {
-    pub struct RegisterOneFlags: u16 {
-        const ZSeries = 0b0000_0000_1000_0000;
-        const Bist = 0b0000_0001_0000_0000;
+
+impl Bist {
+    pub fn from_u8_slice(val: &[u8; 2]) -> Self {
+        match CustomerMemoryArea::Bist.read(*val) {
+            0 => Self::Disabled,
+            _ => Self::Enabled,
+        }
     }
-}
 
-bitflags! {
-    pub struct RegisterTwoFlags: u16 {
-        const TrigInt = 0b1000_0000_0000_0000;
-        const WOCDiff = 0b0001_0000_0000_0000;
-        const ExtTrig = 0b0000_1000_0000_0000;
-        const TcmpEn = 0b0000_0100_0000_0000;
-        const BurstSelZ = 0b0000_0010_0000_0000;
-        const BurstSelY = 0b0000_0001_0000_0000;
-        const BurstSelX = 0b0000_0000_1000_0000;
-        const BurstSelT = 0b0000_0000_0100_0000;
+    /// Writes the setting into the register word `val`, keeping every other bit.
+    pub fn to_u8_slice(self, val: &[u8; 2]) -> [u8; 2] {
+        CustomerMemoryArea::Bist.write(*val, self as u16)
     }
 }
-
-#[derive(Clone, Copy, Format)]
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(usize)]
 pub enum TemperatureCompensation {
     Disabled,
@@ -162,16 +392,21 @@ impl TemperatureCompensation {
     #[bitmatch]
     pub fn from_u8_slice(val: &[u8; 2]) -> Self {
         #[bitmatch]
-        let "????_?t??" = val[1];
+        let "????_?t??" = val[0];
         #[bitmatch]
         match t {
             "1" => Self::Enabled,
             "0" => Self::Disabled,
         }
     }
+
+    /// Writes the setting into the register word `val`, keeping every other bit.
+    pub fn to_u8_slice(self, val: &[u8; 2]) -> [u8; 2] {
+        CustomerMemoryArea::TcmpEn.write(*val, self as u16)
+    }
 }
 
-#[derive(Clone, Copy, Format)]
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(usize)]
 pub enum Gain {
     ZERO,
@@ -198,18 +433,25 @@ impl Gain {
             "?111_????" => Self::SEVEN,
         }
     }
+
+    /// Writes the gain into the register word `val`, keeping every other bit.
+    pub fn to_u8_slice(self, val: &[u8; 2]) -> [u8; 2] {
+        CustomerMemoryArea::GainSel.write(*val, self as u16)
+    }
 }
 
-#[derive(Clone, Copy, Format)]
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Which 16 bits of the 19-bit ADC result an axis outputs, from RES_XYZ = 0
+/// (the lowest bits, finest sensitivity) to RES_XYZ = 3 (the highest bits).
 #[repr(usize)]
 pub enum Resolution {
-    BIT19,
-    BIT18,
-    BIT17,
     BIT16,
+    BIT17,
+    BIT18,
+    BIT19,
 }
 
-#[derive(Clone, Copy, Format)]
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(usize)]
 pub enum HallConf {
     TWOPHASE,
@@ -226,9 +468,18 @@ impl HallConf {
             _ => None,
         }
     }
+
+    /// Writes the configuration into the register word `val`, keeping every other bit.
+    pub fn to_u8_slice(self, val: &[u8; 2]) -> [u8; 2] {
+        let value = match self {
+            Self::TWOPHASE => 0x0,
+            Self::FOURPHASE => 0xC,
+        };
+        CustomerMemoryArea::Hallconf.write(*val, value)
+    }
 }
 
-#[derive(Clone, Copy, Format)]
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Res3D {
     pub x: Resolution,
     pub y: Resolution,
@@ -244,10 +495,10 @@ impl Res3D {
         let "????_??zzv" = val[0];
         let xval = #[bitmatch]
         match x {
-            "00" => Resolution::BIT19,
-            "01" => Resolution::BIT18,
-            "10" => Resolution::BIT17,
-            "11" => Resolution::BIT16,
+            "00" => Resolution::BIT16,
+            "01" => Resolution::BIT17,
+            "10" => Resolution::BIT18,
+            "11" => Resolution::BIT19,
         };
         let yval = #[bitmatch]
         match v {
@@ -255,25 +506,25 @@ impl Res3D {
             {
                 #[bitmatch]
                 match y {
-                    "0" => Resolution::BIT19,
-                    "1" => Resolution::BIT18,
+                    "0" => Resolution::BIT16,
+                    "1" => Resolution::BIT17,
                 }
             }
             "1" =>
             {
                 #[bitmatch]
                 match y {
-                    "0" => Resolution::BIT17,
-                    "1" => Resolution::BIT16,
+                    "0" => Resolution::BIT18,
+                    "1" => Resolution::BIT19,
                 }
             }
         };
         let zval = #[bitmatch]
         match z {
-            "00" => Resolution::BIT19,
-            "01" => Resolution::BIT18,
-            "10" => Resolution::BIT17,
-            "11" => Resolution::BIT16,
+            "00" => Resolution::BIT16,
+            "01" => Resolution::BIT17,
+            "10" => Resolution::BIT18,
+            "11" => Resolution::BIT19,
         };
 
         Self {
@@ -282,7 +533,131 @@ impl Res3D {
             z: zval,
         }
     }
+
+    /// Writes the resolution of every axis into the register word `val`, keeping every other bit.
+    pub fn to_u8_slice(self, val: &[u8; 2]) -> [u8; 2] {
+        let val = CustomerMemoryArea::ResX.write(*val, self.x as u16);
+        let val = CustomerMemoryArea::ResY.write(val, self.y as u16);
+        CustomerMemoryArea::ResZ.write(val, self.z as u16)
+    }
+}
+
+/// Sensitivity drift coefficients for readings below (SENS_TC_LT) and above (SENS_TC_HT) 35°C.
+///
+/// Only applied when temperature compensation is enabled.
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SensTc {
+    pub low: u8,
+    pub high: u8,
+}
+
+/// The part of the sensor configuration needed to turn raw counts into a field.
+#[derive(Clone, Copy, Format, Debug, Serialize, Deserialize)]
+pub struct SensorConfig {
+    pub resolution: Res3D,
+    pub gain: Gain,
+    pub temperature_compensation: TemperatureCompensation,
+    pub hall_configuration: HallConf,
+    pub temp_ref: TempRef,
+    pub sens_tc: SensTc,
+}
+
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(usize)]
+pub enum Oversampling {
+    ZERO,
+    ONE,
+    TWO,
+    THREE,
+}
+
+impl Oversampling {
+    fn from_index(index: u16) -> Self {
+        match index & 0b11 {
+            0 => Self::ZERO,
+            1 => Self::ONE,
+            2 => Self::TWO,
+            _ => Self::THREE,
+        }
+    }
 }
+
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(usize)]
+pub enum DigitalFilter {
+    ZERO,
+    ONE,
+    TWO,
+    THREE,
+    FOUR,
+    FIVE,
+    SIX,
+    SEVEN,
+}
+
+impl DigitalFilter {
+    pub fn from_u8_slice(val: &[u8; 2]) -> Self {
+        match CustomerMemoryArea::DigFilt.read(*val) {
+            0 => Self::ZERO,
+            1 => Self::ONE,
+            2 => Self::TWO,
+            3 => Self::THREE,
+            4 => Self::FOUR,
+            5 => Self::FIVE,
+            6 => Self::SIX,
+            _ => Self::SEVEN,
+        }
+    }
+
+    /// Writes the filter into the register word `val`, keeping every other bit.
+    pub fn to_u8_slice(self, val: &[u8; 2]) -> [u8; 2] {
+        CustomerMemoryArea::DigFilt.write(*val, self as u16)
+    }
+}
+
+/// Time to leave idle before a measurement starts (T_STBY), in microseconds.
+pub const STANDBY_US: u32 = 264;
+/// Time to power up the analog front end (T_ACTIVE), in microseconds.
+pub const ACTIVE_US: u32 = 432;
+
+/// The settings that decide how long a measurement takes.
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConversionSettings {
+    pub oversampling: Oversampling,
+    pub temperature_oversampling: Oversampling,
+    pub digital_filter: DigitalFilter,
+}
+
+impl ConversionSettings {
+    /// Time to convert one magnetic axis (T_CONVM), in microseconds.
+    pub const fn magnetic_conversion_us(&self) -> u32 {
+        let oversampling = 1 << self.oversampling as u32;
+        let filter = 1 << self.digital_filter as u32;
+        67 + 64 * oversampling * (2 + filter)
+    }
+
+    /// Time to convert the temperature (T_CONVT), in microseconds.
+    pub const fn temperature_conversion_us(&self) -> u32 {
+        67 + 192 * (1 << self.temperature_oversampling as u32)
+    }
+
+    /// Time from starting a measurement of `axes` until its data is ready (T_CONV), in microseconds.
+    pub const fn conversion_time_us(&self, axes: BurstSel) -> u32 {
+        let magnetic_axes = axes.x as u32 + axes.y as u32 + axes.z as u32;
+        let mut time = STANDBY_US + ACTIVE_US + magnetic_axes * self.magnetic_conversion_us();
+        if axes.temp {
+            time += self.temperature_conversion_us();
+        }
+        time
+    }
+
+    /// Highest rate, in Hz, at which measurements of `axes` can follow each other.
+    pub fn max_sample_rate(&self, axes: BurstSel) -> f32 {
+        1_000_000.0 / self.conversion_time_us(axes) as f32
+    }
+}
+
+#[derive(Clone, Copy, Format, Debug)]
 #[repr(u32)]
 pub enum CustomerMemoryArea {
     Hallconf,
@@ -319,8 +694,33 @@ struct MemoryLocation {
     length: usize,
 }
 
+impl MemoryLocation {
+    fn mask(&self) -> u16 {
+        (u16::MAX >> (16 - self.length)) << self.position
+    }
+}
+
 impl CustomerMemoryArea {
-    fn to_memory_location(&self) -> MemoryLocation {
+    /// Address of the register holding this field.
+    pub fn register(&self) -> u8 {
+        self.to_memory_location().register
+    }
+
+    /// Extracts this field from the contents of its register.
+    pub fn read(&self, data: [u8; 2]) -> u16 {
+        let location = self.to_memory_location();
+        (u16::from_be_bytes(data) & location.mask()) >> location.position
+    }
+
+    /// Replaces this field in the contents of its register, keeping every other bit.
+    pub fn write(&self, data: [u8; 2], value: u16) -> [u8; 2] {
+        let location = self.to_memory_location();
+        let mask = location.mask();
+        let word = (u16::from_be_bytes(data) & !mask) | ((value << location.position) & mask);
+        word.to_be_bytes()
+    }
+
+    fn to_memory_location(self) -> MemoryLocation {
         match self {
             CustomerMemoryArea::Hallconf => MemoryLocation {
                 register: 0x00,
@@ -450,3 +850,599 @@ impl CustomerMemoryArea {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::fmt::Debug;
+    use std::vec::Vec;
+
+    const BACKGROUNDS: [[u8; 2]; 3] = [[0x00, 0x00], [0xFF, 0xFF], [0xA5, 0x5A]];
+    const WORDS: [u16; 6] = [0x0000, 0x0001, 0x1234, 0x7FFF, 0x8000, 0xFFFF];
+    const GAINS: [Gain; 8] = [
+        Gain::ZERO,
+        Gain::ONE,
+        Gain::TWO,
+        Gain::THREE,
+        Gain::FOUR,
+        Gain::FIVE,
+        Gain::SIX,
+        Gain::SEVEN,
+    ];
+    const OVERSAMPLINGS: [Oversampling; 4] = [
+        Oversampling::ZERO,
+        Oversampling::ONE,
+        Oversampling::TWO,
+        Oversampling::THREE,
+    ];
+    const FILTERS: [DigitalFilter; 8] = [
+        DigitalFilter::ZERO,
+        DigitalFilter::ONE,
+        DigitalFilter::TWO,
+        DigitalFilter::THREE,
+        DigitalFilter::FOUR,
+        DigitalFilter::FIVE,
+        DigitalFilter::SIX,
+        DigitalFilter::SEVEN,
+    ];
+    const RESOLUTIONS: [Resolution; 4] = [
+        Resolution::BIT16,
+        Resolution::BIT17,
+        Resolution::BIT18,
+        Resolution::BIT19,
+    ];
+
+    const RESOLUTION_AREAS: [CustomerMemoryArea; 3] = [
+        CustomerMemoryArea::ResX,
+        CustomerMemoryArea::ResY,
+        CustomerMemoryArea::ResZ,
+    ];
+
+    /// Sets every value on top of every background and checks that it reads back
+    /// and that the bits outside `areas` are untouched.
+    fn round_trip<const R: u8, T: Copy + PartialEq + Debug>(
+        areas: &[CustomerMemoryArea],
+        values: &[T],
+        get: fn(&Register<R>) -> T,
+        set: fn(&mut Register<R>, T) -> &mut Register<R>,
+    ) {
+        let mask = areas
+            .iter()
+            .fold(0, |mask, area| mask | area.to_memory_location().mask());
+        for background in BACKGROUNDS {
+            for &value in values {
+                let before = Register::<R>::new(background);
+                let mut after = before;
+                set(&mut after, value);
+                assert_eq!(get(&after), value);
+                let (before, after) = (
+                    u16::from_be_bytes(before.data()),
+                    u16::from_be_bytes(after.data()),
+                );
+                assert_eq!(
+                    before & !mask,
+                    after & !mask,
+                    "{:?} leaked into other bits",
+                    value
+                );
+            }
+        }
+    }
+
+    fn bits(length: u32) -> Vec<u8> {
+        (0..1u16 << length).map(|value| value as u8).collect()
+    }
+
+    fn bools() -> [bool; 2] {
+        [false, true]
+    }
+
+    fn resolutions() -> Vec<Res3D> {
+        let mut resolutions = Vec::new();
+        for x in RESOLUTIONS {
+            for y in RESOLUTIONS {
+                for z in RESOLUTIONS {
+                    resolutions.push(Res3D { x, y, z });
+                }
+            }
+        }
+        resolutions
+    }
+
+    #[test]
+    fn hall_conf() {
+        round_trip(
+            &[CustomerMemoryArea::Hallconf],
+            &[HallConf::TWOPHASE, HallConf::FOURPHASE],
+            |register: &Register<0x00>| register.hall_conf().unwrap(),
+            Register::set_hall_conf,
+        );
+    }
+
+    #[test]
+    fn unknown_hall_conf() {
+        assert_eq!(Register::<0x00>::new([0x00, 0x05]).hall_conf(), None);
+    }
+
+    #[test]
+    fn gain() {
+        round_trip(
+            &[CustomerMemoryArea::GainSel],
+            &GAINS,
+            Register::<0x00>::gain,
+            Register::set_gain,
+        );
+    }
+
+    #[test]
+    fn zseries() {
+        round_trip(
+            &[CustomerMemoryArea::ZSeries],
+            &[ZSeries::Disabled, ZSeries::Enabled],
+            Register::<0x00>::zseries,
+            Register::set_zseries,
+        );
+    }
+
+    #[test]
+    fn bist() {
+        round_trip(
+            &[CustomerMemoryArea::Bist],
+            &[Bist::Disabled, Bist::Enabled],
+            Register::<0x00>::bist,
+            Register::set_bist,
+        );
+    }
+
+    #[test]
+    fn ana_reserved_low() {
+        round_trip(
+            &[CustomerMemoryArea::AnaReservedLow],
+            &bits(7),
+            Register::<0x00>::ana_reserved_low,
+            Register::set_ana_reserved_low,
+        );
+    }
+
+    #[test]
+    fn burst_data_rate() {
+        round_trip(
+            &[CustomerMemoryArea::BurstDataRate],
+            &bits(6),
+            Register::<0x01>::burst_data_rate,
+            Register::set_burst_data_rate,
+        );
+    }
+
+    #[test]
+    fn burst_sel() {
+        let selections: Vec<_> = (0..16)
+            .map(|bits| BurstSel {
+                x: bits & 1 != 0,
+                y: bits & 2 != 0,
+                z: bits & 4 != 0,
+                temp: bits & 8 != 0,
+            })
+            .collect();
+        round_trip(
+            &[CustomerMemoryArea::BurstSel],
+            &selections,
+            Register::<0x01>::burst_sel,
+            Register::set_burst_sel,
+        );
+    }
+
+    #[test]
+    fn burst_sel_bit_order() {
+        let register = Register::<0x01>::new([0b0000_0010, 0b0100_0000]);
+        let burst_sel = register.burst_sel();
+        assert!(burst_sel.z && burst_sel.temp && !burst_sel.x && !burst_sel.y);
+    }
+
+    #[test]
+    fn temperature_compensation() {
+        round_trip(
+            &[CustomerMemoryArea::TcmpEn],
+            &[
+                TemperatureCompensation::Disabled,
+                TemperatureCompensation::Enabled,
+            ],
+            Register::<0x01>::temperature_compensation,
+            Register::set_temperature_compensation,
+        );
+    }
+
+    #[test]
+    fn external_trigger() {
+        round_trip(
+            &[CustomerMemoryArea::ExtTrg],
+            &bools(),
+            Register::<0x01>::external_trigger,
+            Register::set_external_trigger,
+        );
+    }
+
+    #[test]
+    fn wake_on_change_diff() {
+        round_trip(
+            &[CustomerMemoryArea::WocDiff],
+            &bools(),
+            Register::<0x01>::wake_on_change_diff,
+            Register::set_wake_on_change_diff,
+        );
+    }
+
+    #[test]
+    fn comm_mode() {
+        round_trip(
+            &[CustomerMemoryArea::CommMode],
+            &[CommMode::Both, CommMode::SpiOnly, CommMode::I2cOnly],
+            Register::<0x01>::comm_mode,
+            Register::set_comm_mode,
+        );
+    }
+
+    #[test]
+    fn trigger_interrupt() {
+        round_trip(
+            &[CustomerMemoryArea::TrigInt],
+            &bools(),
+            Register::<0x01>::trigger_interrupt,
+            Register::set_trigger_interrupt,
+        );
+    }
+
+    #[test]
+    fn oversampling() {
+        round_trip(
+            &[CustomerMemoryArea::OSR],
+            &OVERSAMPLINGS,
+            Register::<0x02>::oversampling,
+            Register::set_oversampling,
+        );
+    }
+
+    #[test]
+    fn digital_filter() {
+        round_trip(
+            &[CustomerMemoryArea::DigFilt],
+            &FILTERS,
+            Register::<0x02>::digital_filter,
+            Register::set_digital_filter,
+        );
+    }
+
+    #[test]
+    fn resolution() {
+        round_trip(
+            &RESOLUTION_AREAS,
+            &resolutions(),
+            Register::<0x02>::resolution,
+            Register::set_resolution,
+        );
+    }
+
+    #[test]
+    fn temperature_oversampling() {
+        round_trip(
+            &[CustomerMemoryArea::OSR2],
+            &OVERSAMPLINGS,
+            Register::<0x02>::temperature_oversampling,
+            Register::set_temperature_oversampling,
+        );
+    }
+
+    #[test]
+    fn sens_tc_lt() {
+        round_trip(
+            &[CustomerMemoryArea::SensTcLT],
+            &bits(8),
+            Register::<0x03>::sens_tc_lt,
+            Register::set_sens_tc_lt,
+        );
+    }
+
+    #[test]
+    fn sens_tc_ht() {
+        round_trip(
+            &[CustomerMemoryArea::SensTcHT],
+            &bits(8),
+            Register::<0x03>::sens_tc_ht,
+            Register::set_sens_tc_ht,
+        );
+    }
+
+    #[test]
+    fn offset_x() {
+        round_trip(
+            &[CustomerMemoryArea::OffsetX],
+            &WORDS,
+            Register::<0x04>::offset_x,
+            Register::set_offset_x,
+        );
+    }
+
+    #[test]
+    fn offset_y() {
+        round_trip(
+            &[CustomerMemoryArea::OffsetY],
+            &WORDS,
+            Register::<0x05>::offset_y,
+            Register::set_offset_y,
+        );
+    }
+
+    #[test]
+    fn offset_z() {
+        round_trip(
+            &[CustomerMemoryArea::OffsetZ],
+            &WORDS,
+            Register::<0x06>::offset_z,
+            Register::set_offset_z,
+        );
+    }
+
+    #[test]
+    fn wo_xy_threshold() {
+        round_trip(
+            &[CustomerMemoryArea::WOxyThreshold],
+            &WORDS,
+            Register::<0x07>::wo_xy_threshold,
+            Register::set_wo_xy_threshold,
+        );
+    }
+
+    #[test]
+    fn wo_z_threshold() {
+        round_trip(
+            &[CustomerMemoryArea::WOzThreshold],
+            &WORDS,
+            Register::<0x08>::wo_z_threshold,
+            Register::set_wo_z_threshold,
+        );
+    }
+
+    #[test]
+    fn getters_agree_with_the_bit_decoders() {
+        for background in BACKGROUNDS {
+            for gain in GAINS {
+                let mut register = Register::<0x00>::new(background);
+                register.set_gain(gain);
+                assert_eq!(Gain::from_u8_slice(&register.data()), gain);
+            }
+            for x in RESOLUTIONS {
+                for y in RESOLUTIONS {
+                    let resolution = Res3D { x, y, z: y };
+                    let mut register = Register::<0x02>::new(background);
+                    register.set_resolution(resolution);
+                    assert_eq!(Res3D::from_u8_slice(&register.data()), resolution);
+                }
+            }
+        }
+    }
+
+    /// Encodes every value into every possible register word, and checks that it
+    /// decodes back and that the bits outside `areas` are untouched.
+    fn encodes<T: Copy + PartialEq + Debug>(
+        areas: &[CustomerMemoryArea],
+        values: &[T],
+        decode: fn(&[u8; 2]) -> T,
+        encode: fn(T, &[u8; 2]) -> [u8; 2],
+    ) {
+        let mask = areas
+            .iter()
+            .fold(0, |mask, area| mask | area.to_memory_location().mask());
+        for word in 0..=u16::MAX {
+            let before = word.to_be_bytes();
+            for &value in values {
+                let after = encode(value, &before);
+                assert_eq!(decode(&after), value, "in {:#06x}", word);
+                assert_eq!(word & !mask, u16::from_be_bytes(after) & !mask);
+            }
+        }
+    }
+
+    #[test]
+    fn encodes_gain() {
+        encodes(
+            &[CustomerMemoryArea::GainSel],
+            &GAINS,
+            Gain::from_u8_slice,
+            Gain::to_u8_slice,
+        );
+    }
+
+    #[test]
+    fn resolution_follows_res_xyz() {
+        // RES_X is bits 5-6, RES_Y bits 7-8 and RES_Z bits 9-10.
+        for (res_xyz, resolution) in RESOLUTIONS.into_iter().enumerate() {
+            let res_xyz = res_xyz as u16;
+            let word = (res_xyz << 5 | res_xyz << 7 | res_xyz << 9).to_be_bytes();
+            assert_eq!(
+                Res3D::from_u8_slice(&word),
+                Res3D {
+                    x: resolution,
+                    y: resolution,
+                    z: resolution,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn encodes_resolution() {
+        encodes(
+            &RESOLUTION_AREAS,
+            &resolutions(),
+            Res3D::from_u8_slice,
+            Res3D::to_u8_slice,
+        );
+    }
+
+    #[test]
+    fn encodes_hall_conf() {
+        encodes(
+            &[CustomerMemoryArea::Hallconf],
+            &[HallConf::TWOPHASE, HallConf::FOURPHASE],
+            |val| HallConf::from_u8_slice(val).unwrap(),
+            HallConf::to_u8_slice,
+        );
+    }
+
+    #[test]
+    fn encodes_temperature_compensation() {
+        encodes(
+            &[CustomerMemoryArea::TcmpEn],
+            &[
+                TemperatureCompensation::Disabled,
+                TemperatureCompensation::Enabled,
+            ],
+            TemperatureCompensation::from_u8_slice,
+            TemperatureCompensation::to_u8_slice,
+        );
+    }
+
+    #[test]
+    fn encodes_zseries() {
+        encodes(
+            &[CustomerMemoryArea::ZSeries],
+            &[ZSeries::Disabled, ZSeries::Enabled],
+            ZSeries::from_u8_slice,
+            ZSeries::to_u8_slice,
+        );
+    }
+
+    #[test]
+    fn encodes_bist() {
+        encodes(
+            &[CustomerMemoryArea::Bist],
+            &[Bist::Disabled, Bist::Enabled],
+            Bist::from_u8_slice,
+            Bist::to_u8_slice,
+        );
+    }
+
+    #[test]
+    fn encodes_digital_filter() {
+        encodes(
+            &[CustomerMemoryArea::DigFilt],
+            &FILTERS,
+            DigitalFilter::from_u8_slice,
+            DigitalFilter::to_u8_slice,
+        );
+    }
+
+    #[test]
+    fn encodes_comm_mode() {
+        encodes(
+            &[CustomerMemoryArea::CommMode],
+            &[CommMode::Both, CommMode::SpiOnly, CommMode::I2cOnly],
+            CommMode::from_u8_slice,
+            CommMode::to_u8_slice,
+        );
+    }
+
+    fn settings(
+        oversampling: Oversampling,
+        digital_filter: DigitalFilter,
+        temperature_oversampling: Oversampling,
+    ) -> ConversionSettings {
+        ConversionSettings {
+            oversampling,
+            temperature_oversampling,
+            digital_filter,
+        }
+    }
+
+    const XYZ: BurstSel = BurstSel {
+        x: true,
+        y: true,
+        z: true,
+        temp: false,
+    };
+
+    /// T_CONVM in microseconds, one row per DIG_FILT and one column per OSR, as
+    /// listed in the conversion time table of the MLX90393 datasheet.
+    const MAGNETIC_CONVERSION_TABLE: [(DigitalFilter, [u32; 4]); 8] = [
+        (DigitalFilter::ZERO, [259, 451, 835, 1603]),
+        (DigitalFilter::ONE, [323, 579, 1091, 2115]),
+        (DigitalFilter::TWO, [451, 835, 1603, 3139]),
+        (DigitalFilter::THREE, [707, 1347, 2627, 5187]),
+        (DigitalFilter::FOUR, [1219, 2371, 4675, 9283]),
+        (DigitalFilter::FIVE, [2243, 4419, 8771, 17475]),
+        (DigitalFilter::SIX, [4291, 8515, 16963, 33859]),
+        (DigitalFilter::SEVEN, [8387, 16707, 33347, 66627]),
+    ];
+
+    #[test]
+    fn magnetic_conversion_times() {
+        for (digital_filter, row) in MAGNETIC_CONVERSION_TABLE {
+            for (oversampling, expected) in OVERSAMPLINGS.into_iter().zip(row) {
+                let settings = settings(oversampling, digital_filter, Oversampling::ZERO);
+                assert_eq!(
+                    settings.magnetic_conversion_us(),
+                    expected,
+                    "{:?} {:?}",
+                    oversampling,
+                    digital_filter
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn temperature_conversion_times() {
+        // T_CONVT in microseconds for OSR2 = 0 to 3, from the datasheet.
+        for (temperature_oversampling, expected) in
+            OVERSAMPLINGS.into_iter().zip([259, 451, 835, 1603])
+        {
+            let settings = settings(
+                Oversampling::ZERO,
+                DigitalFilter::ZERO,
+                temperature_oversampling,
+            );
+            assert_eq!(settings.temperature_conversion_us(), expected);
+        }
+    }
+
+    #[test]
+    fn conversion_time_counts_each_axis() {
+        let settings = settings(Oversampling::ZERO, DigitalFilter::TWO, Oversampling::ONE);
+        // T_STBY 264 µs + T_ACTIVE 432 µs + 3 axes of 451 µs.
+        assert_eq!(settings.conversion_time_us(XYZ), 2049);
+        let xyzt = BurstSel { temp: true, ..XYZ };
+        assert_eq!(settings.conversion_time_us(xyzt), 2500);
+        let z = BurstSel {
+            x: false,
+            y: false,
+            ..XYZ
+        };
+        assert_eq!(settings.conversion_time_us(z), 1147);
+    }
+
+    #[test]
+    fn slowest_settings_take_about_200ms() {
+        let settings = settings(
+            Oversampling::THREE,
+            DigitalFilter::SEVEN,
+            Oversampling::THREE,
+        );
+        assert_eq!(settings.conversion_time_us(XYZ), 200_577);
+        assert!((settings.max_sample_rate(XYZ) - 4.985).abs() < 0.01);
+    }
+
+    #[test]
+    fn reads_conversion_settings_from_the_register() {
+        let mut register = Register::<0x02>::new([0xFF, 0xFF]);
+        register
+            .set_oversampling(Oversampling::ONE)
+            .set_digital_filter(DigitalFilter::FIVE)
+            .set_temperature_oversampling(Oversampling::TWO);
+        assert_eq!(
+            register.conversion_settings(),
+            settings(Oversampling::ONE, DigitalFilter::FIVE, Oversampling::TWO)
+        );
+    }
+}
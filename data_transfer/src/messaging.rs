@@ -1,7 +1,11 @@
+use core::marker::PhantomData;
+
 use defmt::write;
-use serde::{Deserialize, Serialize};
+use postcard::ser_flavors::{crc::CrcModifier, Cobs, Slice};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::conversions::MagneticField;
+use crate::conversions::{DecodeError, MagneticBits, MagneticField};
+use crate::memory::{Gain, Oversampling, Res3D, SensorConfig};
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
@@ -17,6 +21,8 @@ pub enum Error {
     FailedCOBSDeserialization,
     FailedWrite,
     FailedRead,
+    FrameOverflow,
+    IncompatibleVersion(u8),
     FailedParse(PostcardError),
 }
 #[derive(Debug)]
@@ -55,39 +61,838 @@ impl From<postcard::Error> for Error {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Reading of a single sensor. Where the sensor sits is described by the
+/// [`crate::layout::BoardLayout`] of the board.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Message {
     pub field: MagneticField,
-    pub position: (f32, f32, f32),
 }
 
 impl Message {
-    pub fn new(field: MagneticField, position: (f32, f32, f32)) -> Self {
-        Self { field, position }
-    }
-    pub fn write_to<T: embedded_io::Write>(&self, writer: &mut T) -> Result<(), Error> {
-        let mut cobs_buffer = [0; 64];
-        //let c = crc::Crc::<u32>::new(&crc::CRC_32_CKSUM);
-        //let crc_serialized = postcard::to_slice_crc32(self, &mut crc_buffer, digest)
-        //   .map_err(|_| Error::FailedCRCSerialization)?;
-        let cobs_serialized = postcard::to_slice_cobs(self, &mut cobs_buffer)
-            .map_err(|_| Error::FailedCOBSSerialization)?;
-        //let _ = postcard::to_eio(cobs_serialized, writer).map_err(|_| Error::FailedWrite)?;
-        writer
-            .write_all(cobs_serialized)
-            .map_err(|_| Error::FailedWrite)?;
+    pub fn new(field: MagneticField) -> Self {
+        Self { field }
+    }
+}
+
+/// Number of sensors on the board, addressed 0x0C to 0x1B.
+pub const BOARD_SENSORS: usize = 16;
+/// Address of the first sensor on the board, the others follow it.
+pub const FIRST_SENSOR_ADDRESS: u8 = 0x0C;
+
+/// Every reading taken during one scan of the board.
+///
+/// Sensors are indexed by their position in the board layout, so the readings
+/// carry no positions of their own.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, defmt::Format)]
+pub struct BoardFrame {
+    /// Bit `i` is set when `fields[i]` was measured during this scan.
+    pub valid: u16,
+    pub fields: [MagneticField; BOARD_SENSORS],
+}
+
+impl BoardFrame {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores the reading of sensor `index` and marks it as valid.
+    pub fn set(&mut self, index: usize, field: MagneticField) {
+        self.fields[index] = field;
+        self.valid |= 1 << index;
+    }
+
+    pub fn get(&self, index: usize) -> Option<&MagneticField> {
+        match self.valid & (1 << index) {
+            0 => None,
+            _ => Some(&self.fields[index]),
+        }
+    }
+
+    /// Iterates over the valid readings along with their sensor index.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &MagneticField)> {
+        (0..BOARD_SENSORS).filter_map(|index| self.get(index).map(|field| (index, field)))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.valid == u16::MAX
+    }
+}
+
+/// Unconverted reading of a single sensor.
+///
+/// Carries the raw 16-bit counts along with the configuration they were taken
+/// with, so the host can convert them, or keep them for later reprocessing.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, defmt::Format)]
+pub struct RawMessage {
+    pub bits: MagneticBits,
+    pub config: SensorConfig,
+}
+
+impl RawMessage {
+    pub fn new(bits: MagneticBits, config: SensorConfig) -> Self {
+        Self { bits, config }
+    }
+
+    pub fn to_field(&self) -> Result<MagneticField, DecodeError> {
+        MagneticField::from_config(self.bits, &self.config)
+    }
+}
+
+/// How the board reports single sensor readings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+pub enum StreamFormat {
+    /// Converted on the device, sent as [`Payload::Field`].
+    #[default]
+    Microtesla,
+    /// Sent as [`Payload::Raw`] for the host to convert.
+    Raw,
+}
+
+/// Everything the board can send to the host.
+// Boxing the board frame is not an option without an allocator.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Payload {
+    Field(Message),
+    Board(BoardFrame),
+    Raw(RawMessage),
+    /// Answer to the [`Command`] whose envelope had the same sequence number.
+    Response(Response),
+    /// Sent in place of a reading when the sensor could not be read.
+    Error(SensorError),
+}
+
+/// How a sensor is triggered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+pub enum MeasurementMode {
+    /// One conversion per request, started by the board.
+    #[default]
+    Single,
+    /// Continuous conversions at the burst data rate.
+    Burst,
+    /// Conversions only reported when the field changes past the thresholds.
+    WakeOnChange,
+}
+
+/// Everything the host can send to the board.
+///
+/// The envelope's `sensor` selects the sensor the command applies to, with 0
+/// meaning every sensor on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+pub enum Command {
+    StartStreaming,
+    StopStreaming,
+    SetFormat(StreamFormat),
+    SetMode(MeasurementMode),
+    SetGain(Gain),
+    SetResolution(Res3D),
+    SetOversampling(Oversampling),
+    /// Register contents are big endian, as in [`crate::memory::Register`].
+    ReadRegister {
+        address: u8,
+    },
+    WriteRegister {
+        address: u8,
+        data: [u8; 2],
+    },
+    Reset,
+    /// Asks the board to describe itself, answered with [`Ack::Handshake`].
+    Handshake,
+    /// Averages `readings` fields into a [`crate::baseline::Baseline`] that the
+    /// sensor subtracts from every later field. Raw readings are left as they are.
+    /// Zero readings clears the baseline.
+    AutoZero {
+        readings: u16,
+    },
+}
+
+/// Successful outcome of a [`Command`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+pub enum Ack {
+    Done,
+    Register {
+        address: u8,
+        data: [u8; 2],
+    },
+    /// The [`crate::layout::BoardLayout::hash`] of the layout the firmware was built with.
+    Handshake {
+        layout_hash: u32,
+    },
+}
+
+/// Why a [`Command`] was refused or failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+pub enum CommandError {
+    /// No sensor at the address given in the envelope.
+    UnknownSensor,
+    /// Outside the customer area of the register map.
+    InvalidRegister,
+    /// The sensor could not run the command.
+    Sensor(SensorError),
+}
+
+/// Why the board could not talk to a sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+pub enum SensorError {
+    /// The I2C transfer failed.
+    Bus(BusError),
+    /// The sensor set ERROR in its status byte, rejecting the command.
+    Device,
+    /// The sensor set SED in its status byte, after a memory error.
+    SingleErrorDetection,
+    /// The sensor set RS in its status byte: it was reset since the last command.
+    Reset,
+    /// The status byte announced `received` data bytes where `expected` were read.
+    DataLength { expected: u8, received: u8 },
+    /// The sensor did not signal a conversion in time.
+    Timeout,
+    /// The INT pin could not be read.
+    Interrupt,
+    /// The register at `register` read back other contents than were written.
+    Verify { register: u8 },
+    /// The reading was out of range for the configuration of the sensor.
+    Decode(DecodeError),
+    /// The configuration needed to convert readings is unknown, like for a Hall
+    /// configuration the conversions do not support.
+    NotConfigured,
+}
+
+/// Kind of I2C failure, as reported by `embedded_hal::i2c::ErrorKind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+pub enum BusError {
+    /// Nobody acknowledged the address or the data, usually a missing sensor.
+    NoAcknowledge,
+    ArbitrationLoss,
+    Bus,
+    Overrun,
+    Other,
+}
+
+pub type Response = Result<Ack, CommandError>;
+
+/// Version of the wire protocol spoken by this crate.
+///
+/// Bump whenever the layout of [`Envelope`] or of any payload changes.
+pub const PROTOCOL_VERSION: u8 = 11;
+
+/// Largest encoded frame, delimiter included. Sized for a full [`BoardFrame`].
+pub const MAX_FRAME_SIZE: usize = 1024;
+
+/// Wrapper carried by every frame on the wire.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    /// Always serialized first so it can be checked before the rest is parsed.
+    pub version: u8,
+    /// I2C address of the sensor the payload came from, or 0 for board-wide payloads.
+    pub sensor: u8,
+    /// Incremented by the sender for every frame, wrapping on overflow.
+    pub sequence: u32,
+    /// Device-side time at which the payload was sampled, in microseconds.
+    pub timestamp_us: u64,
+    pub payload: T,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(sensor: u8, sequence: u32, timestamp_us: u64, payload: T) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            sensor,
+            sequence,
+            timestamp_us,
+            payload,
+        }
+    }
+}
+
+impl<T: Serialize> Envelope<T> {
+    pub fn write_to<W: embedded_io::Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let mut cobs_buffer = [0; MAX_FRAME_SIZE];
+        let frame = encode_frame(self, &mut cobs_buffer)?;
+        writer.write_all(frame).map_err(|_| Error::FailedWrite)?;
         Ok(())
     }
+}
+
+pub(crate) const CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_CKSUM);
+
+/// Serializes `envelope` into `buffer` as a wire frame.
+///
+/// The postcard bytes are followed by their CRC-32 (little endian), and the whole
+/// thing is COBS encoded and terminated with a `0x00` delimiter.
+pub fn encode_frame<'a, T: Serialize>(
+    envelope: &Envelope<T>,
+    buffer: &'a mut [u8],
+) -> Result<&'a mut [u8], Error> {
+    let cobs = Cobs::try_new(Slice::new(buffer)).map_err(|_| Error::FailedCOBSSerialization)?;
+    postcard::serialize_with_flavor(envelope, CrcModifier::new(cobs, CRC.digest()))
+        .map_err(|_| Error::FailedCOBSSerialization)
+}
+
+/// Decodes a single frame produced by [`encode_frame`], without its `0x00` delimiter.
+///
+/// The frame is COBS decoded in place. The checksum is verified before the payload
+/// is parsed, so a corrupted frame is always reported as
+/// [`Error::FailedCRCDeserialization`], and an envelope from another protocol
+/// version as [`Error::IncompatibleVersion`].
+pub fn decode_frame<T: DeserializeOwned>(frame: &mut [u8]) -> Result<Envelope<T>, Error> {
+    let len = cobs::decode_in_place(frame).map_err(|_| Error::FailedCOBSDeserialization)?;
+    let payload_len = len
+        .checked_sub(core::mem::size_of::<u32>())
+        .ok_or(Error::FailedCRCDeserialization)?;
+    let (payload, checksum) = frame[..len].split_at(payload_len);
+    if CRC.checksum(payload).to_le_bytes() != checksum {
+        return Err(Error::FailedCRCDeserialization);
+    }
+    match payload.first() {
+        Some(&version) if version != PROTOCOL_VERSION => Err(Error::IncompatibleVersion(version)),
+        _ => Ok(postcard::from_bytes(payload)?),
+    }
+}
+
+/// Accumulates COBS frames out of an arbitrary byte stream.
+///
+/// Bytes can be fed in chunks of any size: a frame split across several chunks is
+/// reassembled, and a chunk holding several frames yields all of them in order.
+/// Frames longer than `N` bytes are dropped and decoding resumes at the next `0x00`
+/// delimiter, so the decoder resyncs on its own after garbage on the line.
+pub struct FrameDecoder<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+    overflowed: bool,
+    dropped: u32,
+    corrupt: u32,
+}
+
+impl<const N: usize> FrameDecoder<N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            len: 0,
+            overflowed: false,
+            dropped: 0,
+            corrupt: 0,
+        }
+    }
+
+    /// Feeds a single byte, returning the decoded frame when `byte` ends one.
+    pub fn push<T: DeserializeOwned>(&mut self, byte: u8) -> Option<Result<Envelope<T>, Error>> {
+        if byte != 0x00 {
+            match (self.overflowed, self.len < N) {
+                (true, _) => {}
+                (false, true) => {
+                    self.buffer[self.len] = byte;
+                    self.len += 1;
+                }
+                (false, false) => self.overflowed = true,
+            }
+            return None;
+        }
+
+        let len = core::mem::take(&mut self.len);
+        if core::mem::take(&mut self.overflowed) {
+            self.dropped += 1;
+            return Some(Err(Error::FrameOverflow));
+        }
+        if len == 0 {
+            return None;
+        }
+        let frame = decode_frame::<T>(&mut self.buffer[..len]);
+        if frame.is_err() {
+            self.corrupt += 1;
+        }
+        Some(frame)
+    }
+
+    /// Returns an iterator over every frame completed by `chunk`.
+    ///
+    /// Bytes after the last delimiter stay buffered until the next call.
+    pub fn decode<'a, T: DeserializeOwned>(&'a mut self, chunk: &'a [u8]) -> Frames<'a, T, N> {
+        Frames {
+            decoder: self,
+            bytes: chunk.iter(),
+            _frame: PhantomData,
+        }
+    }
+
+    /// Discards any partially received frame.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.overflowed = false;
+    }
+
+    /// Number of frames discarded because they did not fit in the buffer.
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Number of complete frames that failed to decode.
+    pub fn corrupt(&self) -> u32 {
+        self.corrupt
+    }
+}
+
+impl<const N: usize> Default for FrameDecoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Frames<'a, T, const N: usize> {
+    decoder: &'a mut FrameDecoder<N>,
+    bytes: core::slice::Iter<'a, u8>,
+    _frame: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned, const N: usize> Iterator for Frames<'_, T, N> {
+    type Item = Result<Envelope<T>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.bytes.find_map(|byte| self.decoder.push(*byte))
+    }
+}
+
+/// Reads frames from a blocking [`std::io::Read`] through a [`FrameDecoder`].
+#[cfg(feature = "use-std")]
+pub struct FrameReader<R, const N: usize> {
+    reader: R,
+    decoder: FrameDecoder<N>,
+    chunk: [u8; 64],
+    start: usize,
+    end: usize,
+}
+
+#[cfg(feature = "use-std")]
+impl<R: std::io::Read, const N: usize> FrameReader<R, N> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            decoder: FrameDecoder::new(),
+            chunk: [0; 64],
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// Blocks until the next complete frame has been received.
+    ///
+    /// Bytes read past the end of the frame are kept for the following call.
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<Envelope<T>, Error> {
+        loop {
+            while self.start < self.end {
+                let byte = self.chunk[self.start];
+                self.start += 1;
+                if let Some(frame) = self.decoder.push(byte) {
+                    return frame;
+                }
+            }
+            self.start = 0;
+            self.end = 0;
+            match self.reader.read(&mut self.chunk) {
+                Ok(0) => return Err(Error::FailedRead),
+                Ok(n) => self.end = n,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => return Err(Error::FailedRead),
+            }
+        }
+    }
+
+    pub fn decoder(&self) -> &FrameDecoder<N> {
+        &self.decoder
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversions::{Axis, FieldVector, MagneticValue};
+    use crate::memory::{HallConf, Resolution, SensTc, TempRef, TemperatureCompensation};
+
+    /// Encodes an envelope carrying `payload` into `buffer`, returning its length.
+    fn encode(sequence: u32, payload: u16, buffer: &mut [u8]) -> usize {
+        encode_frame(&Envelope::new(0x0C, sequence, 0, payload), buffer)
+            .unwrap()
+            .len()
+    }
+
+    /// Sequence numbers of the frames decoded from `chunk`, `None` for errors.
+    fn sequences<const N: usize>(decoder: &mut FrameDecoder<N>, chunk: &[u8]) -> [Option<u32>; 4] {
+        let mut sequences = [None; 4];
+        for (slot, frame) in sequences.iter_mut().zip(decoder.decode::<u16>(chunk)) {
+            *slot = frame.ok().map(|envelope| envelope.sequence);
+        }
+        sequences
+    }
+
+    /// COBS encodes `content` as the body of a frame, without its delimiter.
+    fn frame_of(content: &[u8], buffer: &mut [u8]) -> usize {
+        cobs::encode(content, buffer)
+    }
+
+    #[test]
+    fn frame_round_trips() {
+        let mut buffer = [0; 32];
+        let len = encode(42, 0xBEEF, &mut buffer);
+        assert_eq!(buffer[len - 1], 0x00);
+        assert!(!buffer[..len - 1].contains(&0x00));
+        let envelope = decode_frame::<u16>(&mut buffer[..len - 1]).unwrap();
+        assert_eq!((envelope.sequence, envelope.payload), (42, 0xBEEF));
+    }
+
+    #[test]
+    fn any_corrupted_byte_fails_the_crc() {
+        let mut buffer = [0; 32];
+        let len = encode(42, 0xBEEF, &mut buffer);
+        let mut content = [0; 32];
+        content[..len - 1].copy_from_slice(&buffer[..len - 1]);
+        let content_len = cobs::decode_in_place(&mut content[..len - 1]).unwrap();
+
+        for index in 0..content_len {
+            for flip in [0x01, 0x80, 0xFF] {
+                let mut corrupted = content;
+                corrupted[index] ^= flip;
+                let mut frame = [0; 64];
+                let frame_len = frame_of(&corrupted[..content_len], &mut frame);
+                assert!(
+                    matches!(
+                        decode_frame::<u16>(&mut frame[..frame_len]),
+                        Err(Error::FailedCRCDeserialization)
+                    ),
+                    "byte {} flipped with {:#04x}",
+                    index,
+                    flip
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn frame_shorter_than_the_crc_is_rejected() {
+        for len in 1..core::mem::size_of::<u32>() {
+            let mut frame = [0; 8];
+            let frame_len = frame_of(&[0x01, 0x02, 0x03][..len], &mut frame);
+            assert!(
+                matches!(
+                    decode_frame::<u16>(&mut frame[..frame_len]),
+                    Err(Error::FailedCRCDeserialization)
+                ),
+                "{} bytes",
+                len
+            );
+        }
+        let mut empty = [0; 2];
+        let empty_len = frame_of(&[], &mut empty);
+        assert!(decode_frame::<u16>(&mut empty[..empty_len]).is_err());
+        assert!(decode_frame::<u16>(&mut []).is_err());
+    }
+
+    #[test]
+    fn envelope_keeps_sequence_and_timestamp() {
+        let mut buffer = [0; 32];
+        let envelope = Envelope::new(0x1B, u32::MAX, 0x0123_4567_89AB_CDEF, 7u16);
+        let len = encode_frame(&envelope, &mut buffer).unwrap().len();
+        let decoded = decode_frame::<u16>(&mut buffer[..len - 1]).unwrap();
+        assert_eq!(decoded.version, PROTOCOL_VERSION);
+        assert_eq!(decoded.sensor, 0x1B);
+        assert_eq!(decoded.sequence, u32::MAX);
+        assert_eq!(decoded.timestamp_us, 0x0123_4567_89AB_CDEF);
+        assert_eq!(decoded.payload, 7);
+    }
+
+    #[test]
+    fn rejects_another_protocol_version() {
+        let mut buffer = [0; 32];
+        let mut envelope = Envelope::new(0x0C, 1, 0, 7u16);
+        envelope.version = PROTOCOL_VERSION + 1;
+        let len = encode_frame(&envelope, &mut buffer).unwrap().len();
+        assert!(matches!(
+            decode_frame::<u16>(&mut buffer[..len - 1]),
+            Err(Error::IncompatibleVersion(version)) if version == PROTOCOL_VERSION + 1
+        ));
+    }
+
+    fn field(x: f64) -> MagneticField {
+        MagneticField {
+            x: Some(MagneticValue::uT(x)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn board_frame_marks_the_readings_it_holds() {
+        let mut frame = BoardFrame::new();
+        assert!(frame.get(0).is_none());
+        assert_eq!(frame.iter().count(), 0);
+
+        frame.set(0, field(1.0));
+        frame.set(15, field(16.0));
+        assert_eq!(frame.valid, 0x8001);
+        assert_eq!(
+            frame.get(15).unwrap().vector(),
+            FieldVector::from_axes(Some(16.0), None, None)
+        );
+        assert!(frame.get(14).is_none());
+        let indices: [Option<usize>; 3] = {
+            let mut iter = frame.iter().map(|(index, _)| index);
+            [iter.next(), iter.next(), iter.next()]
+        };
+        assert_eq!(indices, [Some(0), Some(15), None]);
+        assert!(!frame.is_complete());
+    }
+
+    #[test]
+    fn board_frame_is_complete_once_every_sensor_is_set() {
+        let mut frame = BoardFrame::new();
+        for index in 0..BOARD_SENSORS {
+            assert!(!frame.is_complete());
+            frame.set(index, field(index as f64));
+        }
+        assert!(frame.is_complete());
+        assert!(frame
+            .iter()
+            .all(|(index, field)| field.x.unwrap().microtesla() == index as f64));
+    }
+
+    #[test]
+    fn raw_message_converts_like_the_device() {
+        let bits = MagneticBits::new(
+            Some(0x8123u16.to_be_bytes()),
+            Some(0x7E00u16.to_be_bytes()),
+            None,
+            Some(46500u16.to_be_bytes()),
+        );
+        let config = SensorConfig {
+            resolution: Res3D {
+                x: Resolution::BIT16,
+                y: Resolution::BIT17,
+                z: Resolution::BIT18,
+            },
+            gain: Gain::FIVE,
+            temperature_compensation: TemperatureCompensation::Enabled,
+            hall_configuration: HallConf::FOURPHASE,
+            temp_ref: TempRef::TYPICAL,
+            sens_tc: SensTc {
+                low: 0x20,
+                high: 0x40,
+            },
+        };
+        let expected = MagneticField::from_config(bits, &config).unwrap();
+
+        let mut buffer = [0; 64];
+        let envelope = Envelope::new(0x0C, 1, 0, RawMessage::new(bits, config));
+        let len = encode_frame(&envelope, &mut buffer).unwrap().len();
+        let raw = decode_frame::<RawMessage>(&mut buffer[..len - 1])
+            .unwrap()
+            .payload;
+        let field = raw.to_field().unwrap();
+        assert_eq!(field.vector(), expected.vector());
+        assert!(field.z.is_none());
+        assert_eq!(
+            field.t.map(|t| t.celsius()),
+            expected.t.map(|t| t.celsius())
+        );
+    }
+
+    /// Sends `payload` through a frame and back.
+    fn round_trip<T: Serialize + DeserializeOwned>(payload: T) -> T {
+        let mut buffer = [0; 64];
+        let len = encode_frame(&Envelope::new(0x0C, 1, 0, payload), &mut buffer)
+            .unwrap()
+            .len();
+        decode_frame(&mut buffer[..len - 1]).unwrap().payload
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        let commands = [
+            Command::StartStreaming,
+            Command::StopStreaming,
+            Command::SetFormat(StreamFormat::Raw),
+            Command::SetMode(MeasurementMode::WakeOnChange),
+            Command::SetGain(Gain::THREE),
+            Command::SetResolution(Res3D {
+                x: Resolution::BIT16,
+                y: Resolution::BIT18,
+                z: Resolution::BIT19,
+            }),
+            Command::SetOversampling(Oversampling::TWO),
+            Command::ReadRegister { address: 0x09 },
+            Command::WriteRegister {
+                address: 0x07,
+                data: [0x12, 0x34],
+            },
+            Command::Reset,
+            Command::Handshake,
+            Command::AutoZero { readings: 32 },
+        ];
+        for command in commands {
+            assert_eq!(round_trip(command), command);
+        }
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let responses: [Response; 8] = [
+            Ok(Ack::Done),
+            Ok(Ack::Register {
+                address: 0x02,
+                data: [0xAB, 0xCD],
+            }),
+            Ok(Ack::Handshake {
+                layout_hash: 0xDEAD_BEEF,
+            }),
+            Err(CommandError::UnknownSensor),
+            Err(CommandError::InvalidRegister),
+            Err(CommandError::Sensor(SensorError::Bus(
+                BusError::NoAcknowledge,
+            ))),
+            Err(CommandError::Sensor(SensorError::DataLength {
+                expected: 6,
+                received: 2,
+            })),
+            Err(CommandError::Sensor(SensorError::Verify { register: 0x05 })),
+        ];
+        for response in responses {
+            assert_eq!(round_trip(response), response);
+        }
+        let errors = [
+            SensorError::Device,
+            SensorError::SingleErrorDetection,
+            SensorError::Reset,
+            SensorError::Timeout,
+            SensorError::Interrupt,
+            SensorError::Bus(BusError::Overrun),
+            SensorError::NotConfigured,
+            SensorError::Decode(DecodeError::OutOfRange {
+                axis: Axis::Y,
+                resolution: Resolution::BIT19,
+                counts: -16384,
+            }),
+        ];
+        for error in errors {
+            assert!(matches!(round_trip(Payload::Error(error)), Payload::Error(e) if e == error));
+        }
+    }
+
+    #[test]
+    fn reassembles_a_frame_split_across_pushes() {
+        let mut buffer = [0; 32];
+        let len = encode(7, 1234, &mut buffer);
+        let mut decoder = FrameDecoder::<64>::new();
+        for &byte in &buffer[..len - 1] {
+            assert!(decoder.push::<u16>(byte).is_none());
+        }
+        let envelope = decoder.push::<u16>(buffer[len - 1]).unwrap().unwrap();
+        assert_eq!((envelope.sequence, envelope.payload), (7, 1234));
+
+        let len = encode(8, 5678, &mut buffer);
+        assert_eq!(decoder.decode::<u16>(&buffer[..3]).count(), 0);
+        let envelope = decoder.decode::<u16>(&buffer[3..len]).next().unwrap();
+        assert_eq!(envelope.unwrap().payload, 5678);
+    }
+
+    #[test]
+    fn decodes_several_frames_from_one_chunk() {
+        let mut chunk = [0; 96];
+        let mut len = 0;
+        for sequence in 1..=3 {
+            len += encode(sequence, 0, &mut chunk[len..]);
+        }
+        let mut decoder = FrameDecoder::<64>::new();
+        assert_eq!(
+            sequences(&mut decoder, &chunk[..len]),
+            [Some(1), Some(2), Some(3), None]
+        );
+    }
+
+    #[test]
+    fn resyncs_after_garbage_and_a_missing_delimiter() {
+        let mut chunk = [0; 128];
+        chunk[..3].copy_from_slice(&[0x11, 0x22, 0x00]);
+        let mut len = 3;
+        len += encode(1, 0, &mut chunk[len..]);
+        // A frame cut short runs into the next one, and both are lost.
+        len += encode(2, 0, &mut chunk[len..]) - 1 - 4;
+        len += encode(3, 0, &mut chunk[len..]);
+        len += encode(4, 0, &mut chunk[len..]);
+
+        let mut decoder = FrameDecoder::<64>::new();
+        assert_eq!(
+            sequences(&mut decoder, &chunk[..len]),
+            [None, Some(1), None, Some(4)]
+        );
+        assert_eq!(decoder.corrupt(), 2);
+        assert_eq!(decoder.dropped(), 0);
+    }
+
+    #[test]
+    fn drops_frames_longer_than_the_buffer() {
+        let mut chunk = [0; 96];
+        let mut len = encode_frame(&Envelope::new(0, 1, 0, [0xAAu8; 32]), &mut chunk)
+            .unwrap()
+            .len();
+        len += encode(2, 0, &mut chunk[len..]);
+
+        let mut decoder = FrameDecoder::<16>::new();
+        let mut frames = decoder.decode::<u16>(&chunk[..len]);
+        assert!(matches!(frames.next(), Some(Err(Error::FrameOverflow))));
+        assert_eq!(frames.next().unwrap().unwrap().sequence, 2);
+        assert!(frames.next().is_none());
+        assert_eq!(decoder.dropped(), 1);
+        assert_eq!(decoder.corrupt(), 0);
+    }
+
+    #[test]
+    fn counts_frames_that_fail_to_decode() {
+        let mut buffer = [0; 32];
+        let len = encode(1, 1234, &mut buffer);
+        buffer[2] ^= 0x01;
+        let mut decoder = FrameDecoder::<64>::new();
+        assert!(matches!(
+            decoder.decode::<u16>(&buffer[..len]).next(),
+            Some(Err(Error::FailedCRCDeserialization))
+        ));
+        assert_eq!(decoder.corrupt(), 1);
+        assert_eq!(decoder.dropped(), 0);
+    }
+
+    #[test]
+    fn reset_discards_a_partial_frame() {
+        let mut buffer = [0; 64];
+        let len = encode(1, 0, &mut buffer);
+        let mut decoder = FrameDecoder::<16>::new();
+        assert_eq!(decoder.decode::<u16>(&buffer[..len / 2]).count(), 0);
+        decoder.reset();
+        assert_eq!(sequences(&mut decoder, &buffer[..len])[0], Some(1));
+
+        let mut long = [0; 64];
+        let long_len = encode_frame(&Envelope::new(0, 2, 0, [0xAAu8; 32]), &mut long)
+            .unwrap()
+            .len();
+        assert_eq!(decoder.decode::<u16>(&long[..long_len - 1]).count(), 0);
+        decoder.reset();
+        assert_eq!(sequences(&mut decoder, &buffer[..len])[0], Some(1));
+        assert_eq!(decoder.dropped(), 0);
+        assert_eq!(decoder.corrupt(), 0);
+    }
 
     #[cfg(feature = "use-std")]
-    pub fn read<T: std::io::Read>(reader: &mut T) -> Result<Self, Error> {
-        let mut buffer = [0; 128];
-        let _ = reader.read_exact(&mut buffer);
-        let mut slice = buffer.split_mut(|val| *val == 0x00);
-        let _ = slice.next();
-        let msg = slice.next().ok_or(Error::FailedRead)?;
-
-        let cobs = postcard::take_from_bytes_cobs::<Message>(msg)?;
-        Ok(cobs.0)
+    #[test]
+    fn frame_reader_keeps_bytes_past_the_frame() {
+        let mut stream = [0; 64];
+        let mut len = encode(1, 10, &mut stream);
+        len += encode(2, 20, &mut stream[len..]);
+        let mut reader = FrameReader::<_, 64>::new(&stream[..len]);
+        assert_eq!(reader.read::<u16>().unwrap().payload, 10);
+        assert_eq!(reader.read::<u16>().unwrap().payload, 20);
+        assert!(matches!(reader.read::<u16>(), Err(Error::FailedRead)));
+        assert_eq!(reader.decoder().corrupt(), 0);
     }
 }
@@ -0,0 +1,328 @@
+//! A file format for board sessions, so they can be replayed without the hardware.
+//!
+//! A recording starts with [`MAGIC`] and [`RECORDING_VERSION`], followed by a
+//! [`RecordingHeader`] describing the board, and then one [`RecordedFrame`] per frame received. Each record
+//! is serialized with postcard and COBS encoded, so records are delimited by `0x00`.
+extern crate std;
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::vec::Vec;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::layout::{BoardLayout, SensorPlacement};
+use crate::memory::SensorConfig;
+use crate::messaging::{Envelope, Payload, StreamFormat, PROTOCOL_VERSION};
+
+pub const MAGIC: [u8; 4] = *b"MBRD";
+/// Bumped whenever the layout of the header or of the records changes.
+pub const RECORDING_VERSION: u8 = 2;
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(io::Error),
+    NotARecording,
+    IncompatibleVersion(u8),
+    /// The frames were recorded with another version of the wire protocol.
+    IncompatibleProtocol(u8),
+    FailedParse(postcard::Error),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::Io(err) => write!(f, "{}", err),
+            RecordingError::NotARecording => write!(f, "not a recording"),
+            RecordingError::IncompatibleVersion(version) => {
+                write!(f, "unsupported recording version {}", version)
+            }
+            RecordingError::IncompatibleProtocol(version) => {
+                write!(f, "recorded with protocol version {}", version)
+            }
+            RecordingError::FailedParse(err) => write!(f, "corrupt record: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+impl From<io::Error> for RecordingError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<RecordingError> for io::Error {
+    fn from(value: RecordingError) -> Self {
+        match value {
+            RecordingError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SensorInfo {
+    pub address: u8,
+    pub config: SensorConfig,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub protocol_version: u8,
+    /// When the recording started, in microseconds since the Unix epoch.
+    pub started_at_us: u64,
+    pub format: StreamFormat,
+    /// Where the sensors sat, in the order of the indices of a board frame.
+    pub layout: Vec<SensorPlacement>,
+    /// The sensors that answered when the recording started, and their configuration.
+    pub sensors: Vec<SensorInfo>,
+}
+
+impl RecordingHeader {
+    pub fn new(
+        started_at_us: u64,
+        format: StreamFormat,
+        layout: Vec<SensorPlacement>,
+        sensors: Vec<SensorInfo>,
+    ) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            started_at_us,
+            format,
+            layout,
+            sensors,
+        }
+    }
+
+    /// The layout of the board the recording was made with.
+    pub fn layout(&self) -> BoardLayout<'_> {
+        BoardLayout::new(&self.layout)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// When the host received the frame, in microseconds since the recording started.
+    pub received_us: u64,
+    pub envelope: Envelope<Payload>,
+}
+
+pub struct RecordingWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> RecordingWriter<W> {
+    /// Starts a recording by writing its header.
+    pub fn new(mut writer: W, header: &RecordingHeader) -> Result<Self, RecordingError> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[RECORDING_VERSION])?;
+        let mut recording = Self { writer };
+        recording.write_record(header)?;
+        Ok(recording)
+    }
+
+    pub fn write(&mut self, frame: &RecordedFrame) -> Result<(), RecordingError> {
+        self.write_record(frame)
+    }
+
+    fn write_record<T: Serialize>(&mut self, record: &T) -> Result<(), RecordingError> {
+        let bytes = postcard::to_stdvec_cobs(record).map_err(RecordingError::FailedParse)?;
+        self.writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Iterates over the frames of a recording.
+pub struct RecordingReader<R> {
+    reader: R,
+    header: RecordingHeader,
+    buffer: Vec<u8>,
+}
+
+impl<R: BufRead> RecordingReader<R> {
+    /// Reads the header, leaving the reader at the first frame.
+    pub fn new(mut reader: R) -> Result<Self, RecordingError> {
+        let mut preamble = [0; MAGIC.len() + 1];
+        reader
+            .read_exact(&mut preamble)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => RecordingError::NotARecording,
+                _ => RecordingError::Io(err),
+            })?;
+        if preamble[..MAGIC.len()] != MAGIC {
+            return Err(RecordingError::NotARecording);
+        }
+        if preamble[MAGIC.len()] != RECORDING_VERSION {
+            return Err(RecordingError::IncompatibleVersion(preamble[MAGIC.len()]));
+        }
+
+        let mut buffer = Vec::new();
+        let header: RecordingHeader =
+            read_record(&mut reader, &mut buffer)?.ok_or(RecordingError::NotARecording)?;
+        if header.protocol_version != PROTOCOL_VERSION {
+            return Err(RecordingError::IncompatibleProtocol(
+                header.protocol_version,
+            ));
+        }
+        Ok(Self {
+            reader,
+            header,
+            buffer,
+        })
+    }
+
+    pub fn header(&self) -> &RecordingHeader {
+        &self.header
+    }
+}
+
+impl<R: BufRead> Iterator for RecordingReader<R> {
+    type Item = Result<RecordedFrame, RecordingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        read_record(&mut self.reader, &mut self.buffer).transpose()
+    }
+}
+
+fn read_record<R: BufRead, T: DeserializeOwned>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+) -> Result<Option<T>, RecordingError> {
+    buffer.clear();
+    if reader.read_until(0, buffer)? == 0 {
+        return Ok(None);
+    }
+    postcard::from_bytes_cobs(buffer)
+        .map(Some)
+        .map_err(RecordingError::FailedParse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversions::{MagneticField, MagneticValue};
+    use crate::layout::BOARD_LAYOUT;
+    use crate::memory::{
+        Gain, HallConf, Res3D, Resolution, SensTc, TempRef, TemperatureCompensation,
+    };
+    use crate::messaging::{Message, SensorError};
+
+    fn header() -> RecordingHeader {
+        let config = SensorConfig {
+            resolution: Res3D {
+                x: Resolution::BIT16,
+                y: Resolution::BIT16,
+                z: Resolution::BIT17,
+            },
+            gain: Gain::SEVEN,
+            temperature_compensation: TemperatureCompensation::Disabled,
+            hall_configuration: HallConf::FOURPHASE,
+            temp_ref: TempRef::TYPICAL,
+            sens_tc: SensTc { low: 0, high: 0 },
+        };
+        RecordingHeader::new(
+            1_700_000_000_000_000,
+            StreamFormat::Raw,
+            BOARD_LAYOUT.sensors.to_vec(),
+            std::vec![SensorInfo {
+                address: 0x0C,
+                config,
+            }],
+        )
+    }
+
+    fn recording(frames: &[RecordedFrame]) -> Vec<u8> {
+        let mut writer = RecordingWriter::new(Vec::new(), &header()).unwrap();
+        for frame in frames {
+            writer.write(frame).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    #[test]
+    fn header_and_frames_round_trip() {
+        let field = MagneticField {
+            x: Some(MagneticValue::uT(12.5)),
+            ..Default::default()
+        };
+        let frames = [
+            RecordedFrame {
+                received_us: 0,
+                envelope: Envelope::new(0x0C, 1, 10, Payload::Field(Message::new(field))),
+            },
+            RecordedFrame {
+                received_us: 1500,
+                envelope: Envelope::new(0x0D, 2, 20, Payload::Error(SensorError::Timeout)),
+            },
+        ];
+        let bytes = recording(&frames);
+        assert_eq!(bytes[..MAGIC.len()], MAGIC);
+
+        let reader = RecordingReader::new(bytes.as_slice()).unwrap();
+        let header = reader.header();
+        assert_eq!(header.started_at_us, 1_700_000_000_000_000);
+        assert_eq!(header.format, StreamFormat::Raw);
+        assert_eq!(header.layout, BOARD_LAYOUT.sensors);
+        assert_eq!(header.layout().hash(), BOARD_LAYOUT.hash());
+        assert_eq!(header.sensors[0].address, 0x0C);
+        assert_eq!(header.sensors[0].config.resolution.z, Resolution::BIT17);
+
+        let read: Vec<_> = reader.map(Result::unwrap).collect();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[1].received_us, 1500);
+        assert_eq!(read[0].envelope.sequence, 1);
+        assert!(matches!(
+            read[0].envelope.payload,
+            Payload::Field(message) if message.field.x.unwrap().microtesla() == 12.5
+        ));
+        assert_eq!(read[1].envelope.sensor, 0x0D);
+        assert!(matches!(
+            read[1].envelope.payload,
+            Payload::Error(SensorError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        let mut bytes = recording(&[]);
+        bytes[0] = b'X';
+        assert!(matches!(
+            RecordingReader::new(bytes.as_slice()),
+            Err(RecordingError::NotARecording)
+        ));
+        assert!(matches!(
+            RecordingReader::new(&MAGIC[..2]),
+            Err(RecordingError::NotARecording)
+        ));
+
+        let mut bytes = recording(&[]);
+        bytes[MAGIC.len()] = RECORDING_VERSION + 1;
+        assert!(matches!(
+            RecordingReader::new(bytes.as_slice()),
+            Err(RecordingError::IncompatibleVersion(version)) if version == RECORDING_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_frames_of_another_protocol() {
+        let mut header = header();
+        header.protocol_version = PROTOCOL_VERSION - 1;
+        let bytes = RecordingWriter::new(Vec::new(), &header)
+            .unwrap()
+            .into_inner();
+        assert!(matches!(
+            RecordingReader::new(bytes.as_slice()),
+            Err(RecordingError::IncompatibleProtocol(version)) if version == PROTOCOL_VERSION - 1
+        ));
+    }
+}
@@ -1,8 +1,10 @@
 #![no_std]
 #![no_main]
-mod mlx90393;
 
 use data_transfer::conversions::MagneticField;
+use data_transfer::messaging::{
+    Ack, Command, CommandError, Envelope, FrameDecoder, Payload, MAX_FRAME_SIZE,
+};
 use embedded_io::Write;
 use heapless::{self, String};
 use postcard;
@@ -16,16 +18,16 @@ use embassy_stm32::{
     gpio::{Input, Level, Output, Pin, Pull, Speed},
     i2c, interrupt, peripherals,
     time::hz,
+    Peripheral,
 };
-use embassy_time::Timer;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Delay, Instant, Timer};
 use embedded_hal_async::digital::Wait;
 
 use embassy_stm32::usart;
 use embedded_hal_async::i2c::{I2c, Operation};
 use embedded_hal_bus::util::AtomicCell;
 use mlx90393::sensorgroup::Sensor;
-
-use mlx90393::MLX90393;
 //use embedded_hal::blocking::i2c::Operation;
 use {defmt_rtt as _, panic_probe as _};
 
@@ -38,8 +40,50 @@ bind_interrupts!(
     }
 );
 
+/// Commands decoded from the host, waiting to be applied by the main loop.
+static COMMANDS: Channel<CriticalSectionRawMutex, Envelope<Command>, 4> = Channel::new();
+
+#[embassy_executor::task]
+async fn read_commands(
+    mut rx: usart::UartRx<'static, peripherals::USART1, peripherals::GPDMA1_CH3>,
+) {
+    let mut decoder = FrameDecoder::<MAX_FRAME_SIZE>::new();
+    let mut buffer = [0; 64];
+    loop {
+        let n = match rx.read_until_idle(&mut buffer).await {
+            Ok(n) => n,
+            Err(err) => {
+                debug!("UART read failed: {}", err);
+                continue;
+            }
+        };
+        for frame in decoder.decode::<Command>(&buffer[..n]) {
+            match frame {
+                Ok(envelope) => COMMANDS.send(envelope).await,
+                Err(err) => debug!("Dropped command frame: {}", err),
+            }
+        }
+    }
+}
+
+fn now_us() -> u64 {
+    Instant::now().as_micros()
+}
+
+/// A sensor with its INT line on an EXTI pin.
+async fn stm_sensor<'a, I: I2c, T: Pin>(
+    address: u8,
+    pin: impl Peripheral<P = T> + 'a,
+    ch: impl Peripheral<P = T::ExtiChannel> + 'a,
+    i2c: I,
+) -> Sensor<I, ExtiInput<'a, T>, Delay> {
+    let pin = Input::new(pin, Pull::Down);
+    let interr = ExtiInput::new(pin, ch);
+    Sensor::new(address, interr, i2c, Delay, now_us).await
+}
+
 #[embassy_executor::main]
-async fn main(_spawner: Spawner) {
+async fn main(spawner: Spawner) {
     //let address_write: u8 = 0b0001110;
     //let address_read: u8 = 0b0001111;
     let p = embassy_stm32::init(Default::default());
@@ -70,7 +114,7 @@ async fn main(_spawner: Spawner) {
     let uart_rx = p.PA8;
     let uart_tx = p.PB12;
 
-    let mut uart_interface = usart::Uart::new(
+    let uart_interface = usart::Uart::new(
         p.USART1,
         uart_rx,
         uart_tx,
@@ -80,32 +124,49 @@ async fn main(_spawner: Spawner) {
         usart::Config::default(),
     )
     .unwrap();
+    let (mut uart_tx, uart_rx) = uart_interface.split();
+    spawner.spawn(read_commands(uart_rx)).unwrap();
 
     //let i2c1 = AtomicCell::new(i2c);
     //let i2c2 = AtomicCell::new(i2c);
 
-    //let mut sensor = Sensor::new_stm(0x0C, (6.75, -6.75, 0.0), p.PB0, p.EXTI0, i2c).await;
-    let mut sensor = Sensor::new_stm(0x0D, (6.75, -2.25, 0.0), p.PB14, p.EXTI14, i2c).await;
-    //    (0x0E, (6.75, 2.25, 0.0), p.PB13, p.EXTI13);
-    //   (0x0F, (6.75, 6.75, 0.0), p.PA10, p.EXTI10);
-    //(0x10, (2.25, -6.75, 0.0), p.PB4, p.EXTI4);
-    //(0x11, (2.25, -2.25, 0.0), p.PB3, p.EXTI10);
-    //(0x12, (2.25, 2.25, 0.0), p.PA10, p.EXTI10);
-    //(0x13, (2.25, 6.75, 0.0), p.PA10, p.EXTI10);
-    //(0x14, (-2.25, -6.75, 0.0), p.PA12, p.EXTI12);
-    //(0x15, (-2.25, -2.25, 0.0), p.PB5, p.EXTI5);
-    //(0x16, (-2.25, 2.25, 0.0), p.PA10, p.EXTI10);
-    //(0x17, (-2.25, 6.75, 0.0), p.PA10, p.EXTI10);
-    //(0x18, (-6.75, -6.75, 0.0), p.PA9, p.EXTI9);
-    //(0x19, (-6.75, -2.25, 0.0), p.PA2, p.EXTI2);
-    //(0x1A, (-6.75, 2.25, 0.0), p.PA10, p.EXTI10);
-    //(0x1B, (-6.75, 6.75, 0.0), p.PA10, p.EXTI10);
-
-    //let mut sensor = Sensor::new_stm(0x12, (0.0, 0.0, 0.0), p.PA10, p.EXTI10, i2c).await;
+    // The pin of every sensor is listed in BOARD_LAYOUT.
+    let mut sensor = stm_sensor(0x0D, p.PB14, p.EXTI14, i2c).await;
 
+    let mut streaming = true;
     loop {
-        let val = sensor.send_message(&mut uart_interface).await;
-        debug!("{:#?}", val);
+        let request = match streaming {
+            true => COMMANDS.try_receive().ok(),
+            false => Some(COMMANDS.receive().await),
+        };
+        let Some(request) = request else {
+            let val = sensor.send_message(&mut uart_tx).await;
+            debug!("{:#?}", val);
+            continue;
+        };
+        let response = match request.payload {
+            Command::StartStreaming => {
+                streaming = true;
+                Ok(Ack::Done)
+            }
+            Command::StopStreaming => {
+                streaming = false;
+                Ok(Ack::Done)
+            }
+            _ if request.sensor != 0 && request.sensor != sensor.address() => {
+                Err(CommandError::UnknownSensor)
+            }
+            command => sensor.handle_command(command).await,
+        };
+        let reply = Envelope::new(
+            sensor.address(),
+            request.sequence,
+            now_us(),
+            Payload::Response(response),
+        );
+        if let Err(err) = reply.write_to(&mut uart_tx) {
+            debug!("Failed to send response: {}", err);
+        }
     }
 
     //let mut sens = MLX90393::new(address, interr, i2c);
@@ -1,6 +0,0 @@
-pub mod sensor;
-pub mod sensorgroup;
-pub use sensor::*;
-
-pub mod commands;
-pub mod states;
@@ -0,0 +1,304 @@
+use bitflags::bitflags;
+use data_transfer::conversions::MagneticBits;
+
+bitflags! {
+    /// The values a mode or RM command covers, one bit each in the command byte.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct MagneticFieldReturnFlags: u8 {
+        const X = 0b00000010;
+        const Y = 0b00000100;
+        const Z = 0b00001000;
+        const T = 0b00000001;
+    }
+}
+
+impl MagneticFieldReturnFlags {
+    pub const fn from_axes(x: bool, y: bool, z: bool, temp: bool) -> Self {
+        let mut bits = 0;
+        if x {
+            bits |= Self::X.bits();
+        }
+        if y {
+            bits |= Self::Y.bits();
+        }
+        if z {
+            bits |= Self::Z.bits();
+        }
+        if temp {
+            bits |= Self::T.bits();
+        }
+        Self::from_bits_retain(bits)
+    }
+
+    /// Bytes RM returns for these values, status excluded.
+    pub const fn data_len(&self) -> usize {
+        2 * self.bits().count_ones() as usize
+    }
+
+    /// Splits the data of an RM response, status excluded, into its values.
+    ///
+    /// The sensor sends the values it was asked for in the order of their bits:
+    /// T, X, Y, then Z.
+    pub fn split(&self, data: &[u8]) -> MagneticBits {
+        let mut values = data.chunks_exact(2).map(|value| [value[0], value[1]]);
+        let mut next = |flag| match self.contains(flag) {
+            true => values.next(),
+            false => None,
+        };
+        let temp = next(Self::T);
+        let x = next(Self::X);
+        let y = next(Self::Y);
+        let z = next(Self::Z);
+        MagneticBits::new(x, y, z, temp)
+    }
+}
+
+pub struct SB {
+    axes: MagneticFieldReturnFlags,
+}
+pub struct SW {
+    axes: MagneticFieldReturnFlags,
+}
+pub struct SM {
+    axes: MagneticFieldReturnFlags,
+}
+pub struct RM {
+    axes: MagneticFieldReturnFlags,
+}
+pub struct RR {
+    location: u8,
+}
+pub struct WR {
+    data: [u8; 2],
+    location: u8,
+}
+pub struct EX;
+pub struct HR;
+pub struct HS;
+pub struct RT;
+
+pub struct CommandData<T> {
+    pub command: T,
+}
+
+pub trait RunCommand<T, const M: usize, const N: usize> {
+    fn write_command(&self) -> [u8; M];
+    fn read_buffer(&self) -> [u8; N] {
+        [0; N]
+    }
+    /// Bytes of the response to read, status included. At most `N`, and less
+    /// when the length depends on the arguments of the command.
+    fn read_len(&self) -> usize {
+        N
+    }
+}
+
+impl RunCommand<SB, 1, 1> for CommandData<SB> {
+    fn write_command(&self) -> [u8; 1] {
+        [0b00010000 + self.command.axes.bits()]
+    }
+}
+
+impl RunCommand<SW, 1, 1> for CommandData<SW> {
+    fn write_command(&self) -> [u8; 1] {
+        [0b00100000 + self.command.axes.bits()]
+    }
+}
+
+impl RunCommand<SM, 1, 1> for CommandData<SM> {
+    fn write_command(&self) -> [u8; 1] {
+        [0b00110000 + self.command.axes.bits()]
+    }
+}
+
+impl RunCommand<EX, 1, 1> for CommandData<EX> {
+    fn write_command(&self) -> [u8; 1] {
+        [0b10000000]
+    }
+}
+
+impl RunCommand<RT, 1, 1> for CommandData<RT> {
+    fn write_command(&self) -> [u8; 1] {
+        [0b11110000]
+    }
+}
+
+impl RunCommand<RR, 2, 3> for CommandData<RR> {
+    fn write_command(&self) -> [u8; 2] {
+        [0b01010000, (self.command.location << 2)]
+    }
+}
+
+impl RunCommand<WR, 4, 1> for CommandData<WR> {
+    fn write_command(&self) -> [u8; 4] {
+        [
+            0b01100000,
+            self.command.data[0],
+            self.command.data[1],
+            (self.command.location << 2),
+        ]
+    }
+}
+
+impl RunCommand<HR, 1, 1> for CommandData<HR> {
+    fn write_command(&self) -> [u8; 1] {
+        [0b11010000]
+    }
+}
+
+impl RunCommand<HS, 1, 1> for CommandData<HS> {
+    fn write_command(&self) -> [u8; 1] {
+        [0b11100000]
+    }
+}
+
+/// The longest RM response: the status and four values.
+const RM_RESPONSE: usize = 9;
+
+impl RunCommand<RM, 1, RM_RESPONSE> for CommandData<RM> {
+    fn write_command(&self) -> [u8; 1] {
+        [0b01000000 + self.command.axes.bits()]
+    }
+
+    fn read_len(&self) -> usize {
+        1 + self.command.axes.data_len()
+    }
+}
+
+impl CommandData<RM> {
+    /// The values this RM reads back.
+    pub fn axes(&self) -> MagneticFieldReturnFlags {
+        self.command.axes
+    }
+}
+
+pub struct Command;
+
+impl Command {
+    pub fn start_burst<const X: bool, const Y: bool, const Z: bool, const TEMP: bool>(
+    ) -> CommandData<SB> {
+        let axes = const { MagneticFieldReturnFlags::from_axes(X, Y, Z, TEMP) };
+        CommandData {
+            command: SB { axes },
+        }
+    }
+    pub fn start_wake_on_change<const X: bool, const Y: bool, const Z: bool, const TEMP: bool>(
+    ) -> CommandData<SW> {
+        let axes = const { MagneticFieldReturnFlags::from_axes(X, Y, Z, TEMP) };
+        CommandData {
+            command: SW { axes },
+        }
+    }
+    pub fn single_measurement<const X: bool, const Y: bool, const Z: bool, const TEMP: bool>(
+    ) -> CommandData<SM> {
+        let axes = const { MagneticFieldReturnFlags::from_axes(X, Y, Z, TEMP) };
+        CommandData {
+            command: SM { axes },
+        }
+    }
+    pub fn read_measurement<const X: bool, const Y: bool, const Z: bool, const TEMP: bool>(
+    ) -> CommandData<RM> {
+        let axes = const { MagneticFieldReturnFlags::from_axes(X, Y, Z, TEMP) };
+        CommandData {
+            command: RM { axes },
+        }
+    }
+    pub fn read_register(location: u8) -> CommandData<RR> {
+        CommandData {
+            command: RR { location },
+        }
+    }
+    /// `data` is big endian, as returned by [`Command::read_register`].
+    pub fn write_register(data: [u8; 2], location: u8) -> CommandData<WR> {
+        CommandData {
+            command: WR { data, location },
+        }
+    }
+    pub fn exit() -> CommandData<EX> {
+        CommandData { command: EX }
+    }
+    pub fn memory_recall() -> CommandData<HR> {
+        CommandData { command: HR }
+    }
+    pub fn memory_store() -> CommandData<HS> {
+        CommandData { command: HS }
+    }
+    pub fn reset() -> CommandData<RT> {
+        CommandData { command: RT }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_transfer::memory::{Register, Res3D, Resolution};
+
+    #[test]
+    fn mode_commands_select_the_axes() {
+        assert_eq!(
+            Command::start_burst::<true, true, true, true>().write_command(),
+            [0x1F]
+        );
+        assert_eq!(
+            Command::start_wake_on_change::<false, true, false, true>().write_command(),
+            [0x25]
+        );
+        assert_eq!(
+            Command::single_measurement::<true, false, false, false>().write_command(),
+            [0x32]
+        );
+        assert_eq!(
+            Command::read_measurement::<false, false, true, false>().write_command(),
+            [0x48]
+        );
+    }
+
+    #[test]
+    fn register_commands_shift_the_address() {
+        assert_eq!(Command::read_register(0x02).write_command(), [0x50, 0x08]);
+        assert_eq!(
+            Command::write_register([0x12, 0x34], 0x1F).write_command(),
+            [0x60, 0x12, 0x34, 0x7C]
+        );
+    }
+
+    #[test]
+    fn write_register_sends_the_high_byte_first() {
+        // WR is the command, D15-D8, D7-D0 and then the address, the same big
+        // endian word RR answers with. Swapped bytes would write another value
+        // without the sensor reporting an error.
+        let mut conf3 = Register::<0x02>::new([0x00, 0x00]);
+        conf3.set_resolution(Res3D {
+            x: Resolution::BIT16,
+            y: Resolution::BIT16,
+            z: Resolution::BIT19,
+        });
+        assert_eq!(conf3.data(), 0x0600u16.to_be_bytes());
+        assert_eq!(
+            Command::write_register(conf3.data(), 0x02).write_command(),
+            [0x60, 0x06, 0x00, 0x08]
+        );
+    }
+
+    #[test]
+    fn memory_commands_have_no_arguments() {
+        assert_eq!(Command::memory_recall().write_command(), [0xD0]);
+        assert_eq!(Command::memory_store().write_command(), [0xE0]);
+    }
+
+    #[test]
+    fn read_measurement_reads_two_bytes_per_value() {
+        assert_eq!(
+            Command::read_measurement::<true, true, true, true>().read_len(),
+            9
+        );
+        assert_eq!(
+            Command::read_measurement::<true, false, true, false>().read_len(),
+            5
+        );
+        assert_eq!(
+            Command::read_measurement::<false, false, false, false>().read_len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use data_transfer::memory::{DigitalFilter, Gain, HallConf, Oversampling, Register, Res3D};
+use defmt::Format;
+
+/// Everything [`MLX90393::configure`](crate::MLX90393::configure) writes: how the
+/// sensor converts, the offsets it subtracts and the wake-on-change thresholds.
+///
+/// The fields of registers 0x00 and 0x02 it does not cover, like Z-series or
+/// BIST, keep their contents.
+#[derive(Clone, Copy, Format, Debug, PartialEq, Eq)]
+pub struct Configuration {
+    pub gain: Gain,
+    pub hall_configuration: HallConf,
+    pub resolution: Res3D,
+    pub oversampling: Oversampling,
+    pub temperature_oversampling: Oversampling,
+    pub digital_filter: DigitalFilter,
+    /// OFFSET_X, OFFSET_Y and OFFSET_Z.
+    pub offsets: [u16; 3],
+    /// WOXY_THRESHOLD, for wake-on-change on X and Y.
+    pub wo_xy_threshold: u16,
+    /// WOZ_THRESHOLD, for wake-on-change on Z.
+    pub wo_z_threshold: u16,
+}
+
+impl Configuration {
+    /// The registers holding this configuration, as `(address, contents)`.
+    ///
+    /// `conf1` and `conf3` are the current contents of registers 0x00 and 0x02.
+    pub fn registers(
+        &self,
+        mut conf1: Register<0x00>,
+        mut conf3: Register<0x02>,
+    ) -> [(u8, [u8; 2]); 7] {
+        conf1
+            .set_gain(self.gain)
+            .set_hall_conf(self.hall_configuration);
+        conf3
+            .set_resolution(self.resolution)
+            .set_oversampling(self.oversampling)
+            .set_temperature_oversampling(self.temperature_oversampling)
+            .set_digital_filter(self.digital_filter);
+        let [x, y, z] = self.offsets;
+        [
+            (0x00, conf1.data()),
+            (0x02, conf3.data()),
+            (0x04, x.to_be_bytes()),
+            (0x05, y.to_be_bytes()),
+            (0x06, z.to_be_bytes()),
+            (0x07, self.wo_xy_threshold.to_be_bytes()),
+            (0x08, self.wo_z_threshold.to_be_bytes()),
+        ]
+    }
+}
@@ -0,0 +1,60 @@
+use data_transfer::conversions::DecodeError;
+use data_transfer::messaging::{BusError, CommandError, SensorError};
+use embedded_hal_async::i2c::{self, ErrorKind};
+
+/// Why a command to the sensor failed, with the error of the I2C bus `E`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    /// The I2C transfer failed.
+    Bus(E),
+    /// The sensor set ERROR in its status byte, rejecting the command.
+    Device,
+    /// The sensor set SED in its status byte, after a memory error.
+    SingleErrorDetection,
+    /// The sensor set RS in its status byte: it was reset since the last command.
+    Reset,
+    /// The status byte announced `received` data bytes where `expected` were read.
+    DataLength { expected: u8, received: u8 },
+    /// INT was not raised before the timeout.
+    Timeout,
+    /// The INT pin could not be read.
+    Interrupt,
+    /// The register at `register` read back other contents than were written.
+    Verify { register: u8 },
+    /// The reading was out of range for the configuration of the sensor.
+    Decode(DecodeError),
+    /// The configuration needed to convert readings is unknown, like for a Hall
+    /// configuration the conversions do not support.
+    NotConfigured,
+}
+
+impl<E: i2c::Error> From<Error<E>> for SensorError {
+    fn from(value: Error<E>) -> Self {
+        match value {
+            Error::Bus(err) => SensorError::Bus(match err.kind() {
+                ErrorKind::NoAcknowledge(_) => BusError::NoAcknowledge,
+                ErrorKind::ArbitrationLoss => BusError::ArbitrationLoss,
+                ErrorKind::Bus => BusError::Bus,
+                ErrorKind::Overrun => BusError::Overrun,
+                _ => BusError::Other,
+            }),
+            Error::Device => SensorError::Device,
+            Error::SingleErrorDetection => SensorError::SingleErrorDetection,
+            Error::Reset => SensorError::Reset,
+            Error::DataLength { expected, received } => {
+                SensorError::DataLength { expected, received }
+            }
+            Error::Timeout => SensorError::Timeout,
+            Error::Interrupt => SensorError::Interrupt,
+            Error::Verify { register } => SensorError::Verify { register },
+            Error::Decode(err) => SensorError::Decode(err),
+            Error::NotConfigured => SensorError::NotConfigured,
+        }
+    }
+}
+
+impl<E: i2c::Error> From<Error<E>> for CommandError {
+    fn from(value: Error<E>) -> Self {
+        CommandError::Sensor(value.into())
+    }
+}
@@ -0,0 +1,25 @@
+//! Driver for the MLX90393 magnetometer.
+//!
+//! The driver only relies on the `embedded-hal-async` traits: [`I2c`] for the bus,
+//! [`Wait`] for the INT pin and [`DelayNs`] for the pauses the sensor needs between
+//! commands. The bindings to a given MCU live in the firmware.
+//!
+//! The `sim` feature adds the `sim` module, a simulated sensor to test code built
+//! on the driver without a board.
+//!
+//! [`I2c`]: embedded_hal_async::i2c::I2c
+//! [`Wait`]: embedded_hal_async::digital::Wait
+//! [`DelayNs`]: embedded_hal_async::delay::DelayNs
+#![no_std]
+mod config;
+pub use config::Configuration;
+mod error;
+pub mod sensor;
+pub use error::Error;
+pub mod sensorgroup;
+pub use sensor::*;
+
+pub mod commands;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+pub mod states;
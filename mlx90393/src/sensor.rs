@@ -0,0 +1,501 @@
+use super::commands::{Command, RunCommand};
+use crate::states::Burst;
+use crate::states::Idle;
+use crate::states::Measured;
+use crate::states::Measuring;
+use crate::states::NoMode;
+use crate::states::SingleMeasurement;
+use crate::states::WakeOnChange;
+use data_transfer::conversions::MagneticBits;
+use data_transfer::memory::{Register, SensorConfig};
+
+use super::states::SensorState;
+use crate::config::Configuration;
+use crate::error::Error;
+use bitflags::bitflags;
+use core::future::{poll_fn, Future};
+use core::pin::pin;
+use core::task::Poll;
+use data_transfer::conversions::MagneticField;
+//use bitvec::prelude::*;
+use defmt::{debug, info};
+use embedded_hal_async::delay::DelayNs;
+//use embedded_hal::digital::v2::InputPin;
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::I2c;
+//use heapless::Vec;
+
+bitflags! {
+    struct StatusFlags: u8 {
+        const burst = 0b10000000;
+        const woc = 0b01000000;
+        const sm = 0b00100000;
+        const error = 0b00010000;
+        const sed = 0b00001000;
+        const rs = 0b00000100;
+        const data = 0b00000011;
+    }
+}
+
+#[derive(defmt::Format)]
+pub struct Status {
+    pub burst_mode: bool,
+    pub woc_mode: bool,
+    pub sm_mode: bool,
+    pub error: bool,
+    pub sed: bool,
+    pub rs: bool,
+    pub data: u8,
+}
+
+impl Status {
+    fn from_u8(status: &u8) -> Self {
+        let x = StatusFlags::from_bits_retain(*status);
+        Status {
+            burst_mode: x.contains(StatusFlags::burst),
+            woc_mode: x.contains(StatusFlags::woc),
+            sm_mode: x.contains(StatusFlags::sm),
+            error: x.contains(StatusFlags::error),
+            sed: x.contains(StatusFlags::sed),
+            rs: x.contains(StatusFlags::rs),
+            data: (x & StatusFlags::data).bits(),
+        }
+    }
+
+    /// Fails on the flags the sensor raises, then when `len` data bytes were read
+    /// but the sensor announced another length.
+    fn check<E>(&self, len: usize) -> Result<(), Error<E>> {
+        if self.error {
+            return Err(Error::Device);
+        }
+        if self.sed {
+            return Err(Error::SingleErrorDetection);
+        }
+        if self.rs {
+            return Err(Error::Reset);
+        }
+        // D1-D0: the response holds 2 + 2 * D data bytes.
+        let received = 2 + 2 * self.data;
+        match len {
+            0 => Ok(()),
+            len if len == received as usize => Ok(()),
+            len => Err(Error::DataLength {
+                expected: len as u8,
+                received,
+            }),
+        }
+    }
+}
+
+/// Longest wait for a conversion, above the slowest burst data rate of 1.26 s.
+pub const MEASUREMENT_TIMEOUT_MS: u32 = 2000;
+
+/// Time HR takes to copy the non-volatile memory into the registers.
+pub const MEMORY_RECALL_US: u32 = 1500;
+/// Time HS takes to program the non-volatile memory. The sensor must not be
+/// addressed nor lose power before it is done.
+pub const MEMORY_STORE_MS: u32 = 15;
+
+/// Runs `future` unless `deadline` completes first.
+async fn timeout<T>(
+    future: impl Future<Output = T>,
+    deadline: impl Future<Output = ()>,
+) -> Option<T> {
+    let mut future = pin!(future);
+    let mut deadline = pin!(deadline);
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        deadline.as_mut().poll(cx).map(|()| None)
+    })
+    .await
+}
+
+pub struct MLX90393<I, P, D> {
+    pub address: u8,
+    pub interrupt: P,
+    i2c: I,
+    delay: D,
+    timeout_ms: Option<u32>,
+
+    pub state: Option<SensorConfig>,
+}
+
+impl<I: I2c, P: Wait, D: DelayNs> MLX90393<I, P, D> {
+    pub fn new(address: u8, interrupt: P, i2c: I, delay: D) -> Self {
+        Self {
+            address,
+            interrupt,
+            i2c,
+            delay,
+            timeout_ms: Some(MEASUREMENT_TIMEOUT_MS),
+            state: None,
+        }
+    }
+
+    /// How long to wait for INT before failing with [`Error::Timeout`], or `None`
+    /// to wait for as long as it takes.
+    ///
+    /// Starting a mode sets the timeout back to [`MEASUREMENT_TIMEOUT_MS`], except
+    /// for wake-on-change which only converts once the field changes.
+    pub fn set_timeout(&mut self, timeout_ms: Option<u32>) {
+        self.timeout_ms = timeout_ms;
+    }
+
+    pub async fn run_command<C, T, const M: usize, const N: usize>(
+        &mut self,
+        command: C,
+    ) -> Result<(Status, [u8; N]), Error<I::Error>>
+    where
+        C: RunCommand<T, M, N>,
+    {
+        let commands = command.write_command();
+        let mut buffer = command.read_buffer();
+        let len = command.read_len();
+        self.i2c
+            .write_read(self.address, &commands, &mut buffer[..len])
+            .await
+            .map_err(Error::Bus)?;
+        let status = Status::from_u8(&buffer[0]);
+        status.check(len - 1)?;
+
+        Ok((status, buffer))
+    }
+
+    /// Resets the sensor. RS is expected here, so it is not reported as an error.
+    pub async fn reset(&mut self) -> Result<(), Error<I::Error>> {
+        let expected = |result| match result {
+            Ok(_) | Err(Error::Reset) => Ok(()),
+            Err(err) => Err(err),
+        };
+        expected(self.exit().await)?;
+        let reset = Command::reset();
+        expected(self.run_command(reset).await.map(|_| ()))?;
+        self.delay.delay_us(1500).await;
+        Ok(())
+    }
+
+    pub async fn set_sm<const X: bool, const Y: bool, const Z: bool, const TEMP: bool>(
+        &mut self,
+    ) -> Result<(), Error<I::Error>> {
+        info!("Settings Mode to Single Measurement.");
+        self.run_command(Command::single_measurement::<X, Y, Z, TEMP>())
+            .await?;
+        self.timeout_ms = Some(MEASUREMENT_TIMEOUT_MS);
+        Ok(())
+    }
+
+    pub async fn read_register<const R: u8>(&mut self) -> Result<Register<R>, Error<I::Error>> {
+        Ok(Register::<R>::new(self.read_register_at(R).await?))
+    }
+
+    /// Register reads are answered right away, they wait for no conversion.
+    pub async fn read_register_at(&mut self, address: u8) -> Result<[u8; 2], Error<I::Error>> {
+        let command = Command::read_register(address);
+        let (_status, data) = self.run_command(command).await?;
+        let [_, data1, data2] = data;
+        Ok([data1, data2])
+    }
+
+    pub async fn write_register_at(
+        &mut self,
+        address: u8,
+        data: [u8; 2],
+    ) -> Result<(), Error<I::Error>> {
+        self.run_command(Command::write_register(data, address))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn exit(&mut self) -> Result<(), Error<I::Error>> {
+        self.run_command(Command::exit()).await?;
+        self.delay.delay_us(1000).await;
+        Ok(())
+    }
+
+    /// Loads the registers from the non-volatile memory (HR). The sensor must be idle.
+    pub async fn memory_recall(&mut self) -> Result<(), Error<I::Error>> {
+        self.run_command(Command::memory_recall()).await?;
+        self.delay.delay_us(MEMORY_RECALL_US).await;
+        Ok(())
+    }
+
+    /// Saves the registers to the non-volatile memory (HS), so they survive a reset
+    /// or a power cycle. The sensor must be idle.
+    pub async fn memory_store(&mut self) -> Result<(), Error<I::Error>> {
+        self.run_command(Command::memory_store()).await?;
+        self.delay.delay_ms(MEMORY_STORE_MS).await;
+        Ok(())
+    }
+
+    /// Leaves any mode and writes `config` to the registers, then reads them back.
+    ///
+    /// With `persist`, also stores the registers in the non-volatile memory and
+    /// recalls them, checking the stored copy as well. Fails with
+    /// [`Error::Verify`] on the first register that does not hold what was written.
+    pub async fn configure(
+        &mut self,
+        config: &Configuration,
+        persist: bool,
+    ) -> Result<(), Error<I::Error>> {
+        self.exit().await?;
+        let conf1 = self.read_register::<0x00>().await?;
+        let conf3 = self.read_register::<0x02>().await?;
+        let registers = config.registers(conf1, conf3);
+        for (address, data) in registers {
+            self.write_register_at(address, data).await?;
+        }
+        self.verify(&registers).await?;
+        if persist {
+            self.memory_store().await?;
+            self.memory_recall().await?;
+            self.verify(&registers).await?;
+        }
+        self.set_measurement_configuration().await?;
+        Ok(())
+    }
+
+    async fn verify(&mut self, registers: &[(u8, [u8; 2])]) -> Result<(), Error<I::Error>> {
+        for &(register, data) in registers {
+            if self.read_register_at(register).await? != data {
+                return Err(Error::Verify { register });
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn set_measurement_configuration(&mut self) -> Result<&mut Self, Error<I::Error>> {
+        self.state = self.get_measurement_configuration().await?;
+        debug!("State: {}", self.state);
+        Ok(self)
+    }
+
+    /// The configuration conversions depend on, or `None` if the sensor is set to a
+    /// Hall configuration the conversions do not support.
+    pub async fn get_measurement_configuration(
+        &mut self,
+    ) -> Result<Option<SensorConfig>, Error<I::Error>> {
+        let data_bits = &self.read_register::<0x00>().await?;
+        let gain = data_bits.gain();
+        let Some(hall_configuration) = data_bits.hall_conf() else {
+            return Ok(None);
+        };
+
+        let data_bits = &self.read_register::<0x02>().await?;
+        let resolution = data_bits.resolution();
+
+        let data_bits = &self.read_register::<0x01>().await?;
+        let temperature_compensation = data_bits.temperature_compensation();
+
+        let data_bits = &self.read_register::<0x03>().await?;
+        let sens_tc = data_bits.sens_tc();
+
+        let data_bits = &self.read_register::<0x24>().await?;
+        let temp_ref = data_bits.temperature_reference();
+
+        Ok(Some(SensorConfig {
+            resolution,
+            gain,
+            hall_configuration,
+            temperature_compensation,
+            temp_ref,
+            sens_tc,
+        }))
+    }
+
+    pub async fn set_woc<const X: bool, const Y: bool, const Z: bool, const TEMP: bool>(
+        &mut self,
+    ) -> Result<(), Error<I::Error>> {
+        info!("Settings Mode to Wake On Change.");
+        self.run_command(Command::start_wake_on_change::<X, Y, Z, TEMP>())
+            .await?;
+        self.timeout_ms = None;
+        Ok(())
+    }
+    pub async fn set_burst<const X: bool, const Y: bool, const Z: bool, const TEMP: bool>(
+        &mut self,
+    ) -> Result<(), Error<I::Error>> {
+        info!("Settings Mode to Burst.");
+        let (status, _) = self
+            .run_command(Command::start_burst::<X, Y, Z, TEMP>())
+            .await?;
+        info!("{:#?}", status);
+        self.timeout_ms = Some(MEASUREMENT_TIMEOUT_MS);
+        Ok(())
+    }
+    pub async fn set_single_measurmenet<
+        const X: bool,
+        const Y: bool,
+        const Z: bool,
+        const TEMP: bool,
+    >(
+        &mut self,
+    ) -> Result<(), Error<I::Error>> {
+        info!("Settings Mode to Single Measurement.");
+        self.run_command(Command::single_measurement::<X, Y, Z, TEMP>())
+            .await?;
+        self.timeout_ms = Some(MEASUREMENT_TIMEOUT_MS);
+        Ok(())
+    }
+
+    /// Waits for INT to signal a conversion, for at most the timeout.
+    async fn wait_for_data(&mut self) -> Result<(), Error<I::Error>> {
+        let ready = self.interrupt.wait_for_high();
+        let result = match self.timeout_ms {
+            Some(ms) => timeout(ready, self.delay.delay_ms(ms))
+                .await
+                .ok_or(Error::Timeout)?,
+            None => ready.await,
+        };
+        result.map_err(|_| Error::Interrupt)
+    }
+
+    pub async fn get_measurement<const X: bool, const Y: bool, const Z: bool, const TEMP: bool>(
+        &mut self,
+    ) -> Result<(Status, MagneticBits), Error<I::Error>> {
+        self.wait_for_data().await?;
+        let command = Command::read_measurement::<X, Y, Z, TEMP>();
+        let axes = command.axes();
+        let (status, buffer) = self.run_command(command).await?;
+        Ok((status, axes.split(&buffer[1..])))
+    }
+
+    /// Reads a measurement and converts it. Fails with [`Error::NotConfigured`] when
+    /// the configuration is unknown, and with [`Error::Decode`] when the counts are
+    /// out of range.
+    pub async fn get_field<const X: bool, const Y: bool, const Z: bool, const TEMP: bool>(
+        &mut self,
+    ) -> Result<(Status, MagneticField), Error<I::Error>> {
+        let state = self.state;
+        let (status, mbits) = self.get_measurement::<X, Y, Z, TEMP>().await?;
+        let state = state.ok_or(Error::NotConfigured)?;
+        let field = MagneticField::from_config(mbits, &state).map_err(Error::Decode)?;
+        Ok((status, field))
+    }
+
+    pub async fn has_measured(&mut self) -> Result<(), Error<I::Error>> {
+        self.wait_for_data().await
+    }
+}
+
+pub struct Sensor<S, T, I, P, D> {
+    state: SensorState<S, T>,
+    internal: MLX90393<I, P, D>,
+}
+
+impl<S, T, I: I2c, P: Wait, D: DelayNs> Sensor<S, T, I, P, D> {
+    pub async fn reset(mut self) -> Result<Sensor<Idle, NoMode, I, P, D>, Error<I::Error>> {
+        self.internal.reset().await?;
+        Ok(Sensor {
+            state: SensorState {
+                state: Idle,
+                mode: NoMode,
+            },
+            internal: self.internal,
+        })
+    }
+}
+
+impl<I: I2c, P: Wait, D: DelayNs> Sensor<Idle, NoMode, I, P, D> {
+    pub async fn new(
+        address: u8,
+        interrupt: P,
+        i2c: I,
+        delay: D,
+    ) -> Result<Sensor<Idle, NoMode, I, P, D>, Error<I::Error>> {
+        let sensor = Sensor {
+            state: SensorState {
+                state: Idle,
+                mode: NoMode,
+            },
+            internal: MLX90393::new(address, interrupt, i2c, delay),
+        };
+        sensor.reset().await
+    }
+}
+
+impl<I: I2c, P: Wait, D: DelayNs> Sensor<Idle, NoMode, I, P, D> {
+    pub async fn single_measurement<
+        const X: bool,
+        const Y: bool,
+        const Z: bool,
+        const TEMP: bool,
+    >(
+        mut self,
+    ) -> Result<Sensor<Measuring, SingleMeasurement, I, P, D>, Error<I::Error>> {
+        self.internal
+            .set_single_measurmenet::<X, Y, Z, TEMP>()
+            .await?;
+        Ok(Sensor {
+            state: self.state.into(),
+            internal: self.internal,
+        })
+    }
+
+    pub async fn burst<const X: bool, const Y: bool, const Z: bool, const TEMP: bool>(
+        mut self,
+    ) -> Result<Sensor<Measuring, Burst, I, P, D>, Error<I::Error>> {
+        self.internal.set_burst::<X, Y, Z, TEMP>().await?;
+        Ok(Sensor {
+            state: self.state.into(),
+            internal: self.internal,
+        })
+    }
+
+    pub async fn wake_on_change<const X: bool, const Y: bool, const Z: bool, const TEMP: bool>(
+        mut self,
+    ) -> Result<Sensor<Measuring, WakeOnChange, I, P, D>, Error<I::Error>> {
+        self.internal.set_woc::<X, Y, Z, TEMP>().await?;
+        Ok(Sensor {
+            state: self.state.into(),
+            internal: self.internal,
+        })
+    }
+}
+
+impl<T, I: I2c, P: Wait, D: DelayNs> Sensor<Measuring, T, I, P, D> {
+    pub async fn has_measured(mut self) -> Result<Sensor<Measured, T, I, P, D>, Error<I::Error>> {
+        self.internal.has_measured().await?;
+        Ok(Sensor {
+            state: self.state.into(),
+            internal: self.internal,
+        })
+    }
+}
+
+impl<I: I2c, P: Wait, D: DelayNs> Sensor<Measured, Burst, I, P, D> {
+    pub async fn has_measured(self) -> Sensor<Measuring, Burst, I, P, D> {
+        Sensor {
+            state: self.state.into(),
+            internal: self.internal,
+        }
+    }
+}
+
+impl<I: I2c, P: Wait, D: DelayNs> Sensor<Measured, WakeOnChange, I, P, D> {
+    pub async fn has_measured(self) -> Sensor<Measuring, WakeOnChange, I, P, D> {
+        Sensor {
+            state: self.state.into(),
+            internal: self.internal,
+        }
+    }
+}
+
+impl<I: I2c, P: Wait, D: DelayNs> Sensor<Measured, SingleMeasurement, I, P, D> {
+    pub async fn has_measured(self) -> Sensor<Idle, NoMode, I, P, D> {
+        Sensor {
+            state: self.state.into(),
+            internal: self.internal,
+        }
+    }
+}
+
+impl<T, I: I2c, P: Wait, D: DelayNs> Sensor<Measured, T, I, P, D> {
+    pub async fn exit(self) -> Sensor<Idle, NoMode, I, P, D> {
+        Sensor {
+            state: self.state.into(),
+            internal: self.internal,
+        }
+    }
+}
@@ -0,0 +1,458 @@
+use data_transfer::{
+    baseline::{Baseline, BaselineCapture},
+    conversions::MagneticField,
+    layout::BOARD_LAYOUT,
+    memory::Register,
+    messaging::{
+        Ack, BoardFrame, Command, CommandError, Envelope, MeasurementMode, Message, Payload,
+        RawMessage, Response, SensorError, StreamFormat,
+    },
+};
+use defmt::warn;
+use embedded_hal_async::{delay::DelayNs, digital::Wait, i2c::I2c};
+use embedded_io::Write;
+
+use super::sensor::MLX90393;
+use crate::error::Error;
+
+/// Registers 0x00 to 0x1F are free for customer use.
+const CUSTOMER_REGISTERS: u8 = 0x20;
+/// Every register can be read, including the Melexis area holding TREF.
+const REGISTERS: u8 = 0x40;
+
+/// Microseconds since the board booted, used to timestamp envelopes.
+pub type Clock = fn() -> u64;
+
+pub struct Sensor<I, P, D> {
+    mlx: MLX90393<I, P, D>,
+    clock: Clock,
+    sequence: u32,
+    format: StreamFormat,
+    mode: MeasurementMode,
+    /// Subtracted from every field, once captured with [`Command::AutoZero`].
+    baseline: Option<Baseline>,
+}
+
+impl<I: I2c, P: Wait, D: DelayNs + Clone> Sensor<I, P, D> {
+    pub async fn new(address: u8, interrupt: P, i2c: I, mut delay: D, clock: Clock) -> Self {
+        let mlx = MLX90393::new(address, interrupt, i2c, delay.clone());
+
+        let mut sensor = Self {
+            mlx,
+            clock,
+            sequence: 0,
+            format: StreamFormat::default(),
+            mode: MeasurementMode::default(),
+            baseline: None,
+        };
+        // Lets the sensor power up before the first command.
+        delay.delay_ms(100).await;
+        if let Err(err) = sensor.mlx.reset().await {
+            warn!(
+                "Sensor {=u8:#x} did not reset: {}",
+                address,
+                SensorError::from(err)
+            );
+        }
+        if let Err(err) = sensor.mlx.set_measurement_configuration().await {
+            warn!(
+                "Sensor {=u8:#x} could not be configured: {}",
+                address,
+                SensorError::from(err)
+            );
+        }
+        sensor
+    }
+
+    pub fn address(&self) -> u8 {
+        self.mlx.address
+    }
+
+    /// Reloads the configuration and restarts the mode, after the sensor was reset
+    /// or could not be configured.
+    async fn configure(&mut self) -> Result<(), Error<I::Error>> {
+        self.mlx.set_measurement_configuration().await?;
+        self.set_mode(self.mode).await
+    }
+
+    /// Starts a conversion when the sensor is not converting on its own.
+    async fn trigger(&mut self) -> Result<(), Error<I::Error>> {
+        if self.mlx.state.is_none() {
+            self.configure().await?;
+        }
+        if self.mode == MeasurementMode::Single {
+            self.mlx
+                .set_single_measurmenet::<true, true, true, true>()
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn measure(&mut self) -> Result<MagneticField, Error<I::Error>> {
+        self.trigger().await?;
+        let (_status, field) = self.mlx.get_field::<true, true, true, true>().await?;
+        Ok(match self.baseline {
+            Some(baseline) => baseline.subtract(field),
+            None => field,
+        })
+    }
+
+    /// Captures the baseline from `readings` fields, or clears it for none.
+    async fn auto_zero(&mut self, readings: u16) -> Result<(), Error<I::Error>> {
+        self.baseline = None;
+        if readings == 0 {
+            return Ok(());
+        }
+        let mut capture = BaselineCapture::default();
+        for _ in 0..readings {
+            capture.add(&self.measure().await?);
+        }
+        self.baseline = Some(capture.baseline());
+        Ok(())
+    }
+
+    /// Takes a measurement without converting it, along with the configuration the
+    /// host needs to convert it.
+    pub async fn measure_raw(&mut self) -> Result<RawMessage, Error<I::Error>> {
+        self.trigger().await?;
+        let (_status, bits) = self.mlx.get_measurement::<true, true, true, true>().await?;
+        let config = self.mlx.state.ok_or(Error::NotConfigured)?;
+        Ok(RawMessage::new(bits, config))
+    }
+
+    pub fn set_format(&mut self, format: StreamFormat) {
+        self.format = format;
+    }
+
+    pub async fn set_mode(&mut self, mode: MeasurementMode) -> Result<(), Error<I::Error>> {
+        self.mlx.exit().await?;
+        self.mode = mode;
+        match mode {
+            MeasurementMode::Single => Ok(()),
+            MeasurementMode::Burst => self.mlx.set_burst::<true, true, true, true>().await,
+            MeasurementMode::WakeOnChange => self.mlx.set_woc::<true, true, true, true>().await,
+        }
+    }
+
+    /// Applies a command addressed to this sensor.
+    ///
+    /// Streaming is controlled by the caller, so those commands are only acknowledged.
+    pub async fn handle_command(&mut self, command: Command) -> Response {
+        match command {
+            Command::StartStreaming | Command::StopStreaming => Ok(Ack::Done),
+            Command::SetFormat(format) => {
+                self.set_format(format);
+                Ok(Ack::Done)
+            }
+            Command::SetMode(mode) => {
+                self.set_mode(mode).await?;
+                Ok(Ack::Done)
+            }
+            Command::SetGain(gain) => {
+                self.update_register(|register: &mut Register<0x00>| {
+                    register.set_gain(gain);
+                })
+                .await
+            }
+            Command::SetResolution(resolution) => {
+                self.update_register(|register: &mut Register<0x02>| {
+                    register.set_resolution(resolution);
+                })
+                .await
+            }
+            Command::SetOversampling(oversampling) => {
+                self.update_register(|register: &mut Register<0x02>| {
+                    register.set_oversampling(oversampling);
+                })
+                .await
+            }
+            Command::ReadRegister { address } if address < REGISTERS => {
+                let data = self.mlx.read_register_at(address).await?;
+                Ok(Ack::Register { address, data })
+            }
+            Command::WriteRegister { address, data } if address < CUSTOMER_REGISTERS => {
+                self.mlx.exit().await?;
+                let written = self.mlx.write_register_at(address, data).await;
+                let configured = self.configure().await;
+                written?;
+                configured?;
+                Ok(Ack::Done)
+            }
+            Command::ReadRegister { .. } | Command::WriteRegister { .. } => {
+                Err(CommandError::InvalidRegister)
+            }
+            Command::Reset => {
+                self.mode = MeasurementMode::Single;
+                self.mlx.reset().await?;
+                self.mlx.set_measurement_configuration().await?;
+                Ok(Ack::Done)
+            }
+            Command::Handshake => Ok(Ack::Handshake {
+                layout_hash: BOARD_LAYOUT.hash(),
+            }),
+            Command::AutoZero { readings } => {
+                self.auto_zero(readings).await?;
+                Ok(Ack::Done)
+            }
+        }
+    }
+
+    /// Read-modify-writes register `R`, then reloads the configuration used for conversions.
+    async fn update_register<const R: u8>(
+        &mut self,
+        update: impl FnOnce(&mut Register<R>),
+    ) -> Response {
+        let written = self.write_register(update).await;
+        let configured = self.configure().await;
+        written?;
+        configured?;
+        Ok(Ack::Done)
+    }
+
+    async fn write_register<const R: u8>(
+        &mut self,
+        update: impl FnOnce(&mut Register<R>),
+    ) -> Result<(), Error<I::Error>> {
+        self.mlx.exit().await?;
+        let mut register = self.mlx.read_register::<R>().await?;
+        update(&mut register);
+        self.mlx.write_register_at(R, register.data()).await
+    }
+
+    /// The reading of the sensor, or why it could not be read.
+    ///
+    /// A reset sensor has lost its configuration, which is reloaded before the
+    /// next reading.
+    async fn payload(&mut self) -> Payload {
+        let payload = match self.format {
+            StreamFormat::Microtesla => self
+                .measure()
+                .await
+                .map(|field| Payload::Field(Message::new(field))),
+            StreamFormat::Raw => self.measure_raw().await.map(Payload::Raw),
+        };
+        match payload {
+            Ok(payload) => payload,
+            Err(err) => {
+                if matches!(err, Error::Reset) {
+                    self.mlx.state = None;
+                }
+                let err = SensorError::from(err);
+                warn!(
+                    "Sensor {=u8:#x} could not be read: {}",
+                    self.mlx.address, err
+                );
+                Payload::Error(err)
+            }
+        }
+    }
+
+    pub async fn send_message<W: Write>(
+        &mut self,
+        writer: &mut W,
+    ) -> Result<(), data_transfer::messaging::Error> {
+        let payload = self.payload().await;
+        let timestamp_us = (self.clock)();
+        let message = Envelope::new(self.mlx.address, self.sequence, timestamp_us, payload);
+        self.sequence = self.sequence.wrapping_add(1);
+        message.write_to(writer)
+    }
+}
+
+/// Sends every reading of one board scan as a single frame.
+pub fn send_board_frame<W: Write>(
+    writer: &mut W,
+    sequence: u32,
+    timestamp_us: u64,
+    frame: BoardFrame,
+) -> Result<(), data_transfer::messaging::Error> {
+    Envelope::new(0, sequence, timestamp_us, Payload::Board(frame)).write_to(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{Device, NoDelay};
+    use data_transfer::conversions::{Axis, DecodeError};
+    use data_transfer::memory::{Gain, Oversampling, Res3D, Resolution};
+    use data_transfer::messaging::{BusError, FrameDecoder, MAX_FRAME_SIZE};
+    use futures::executor::block_on;
+
+    const ADDRESS: u8 = 0x0C;
+
+    fn sent<I: I2c, P: Wait, D: DelayNs + Clone>(sensor: &mut Sensor<I, P, D>) -> Payload {
+        let mut buffer = [0; MAX_FRAME_SIZE];
+        let len = {
+            let mut writer = &mut buffer[..];
+            block_on(sensor.send_message(&mut writer)).unwrap();
+            MAX_FRAME_SIZE - writer.len()
+        };
+        let mut decoder = FrameDecoder::<MAX_FRAME_SIZE>::new();
+        let mut frames = decoder.decode::<Payload>(&buffer[..len]);
+        frames.next().unwrap().unwrap().payload
+    }
+
+    #[test]
+    fn sends_an_error_frame_when_the_sensor_is_missing() {
+        let device = Device::new(ADDRESS);
+        let mut sensor = block_on(Sensor::new(
+            ADDRESS,
+            device.interrupt(),
+            device.bus(),
+            NoDelay,
+            || 0,
+        ));
+        assert!(matches!(sent(&mut sensor), Payload::Field(_)));
+
+        device.set_connected(false);
+        assert!(matches!(
+            sent(&mut sensor),
+            Payload::Error(SensorError::Bus(BusError::NoAcknowledge))
+        ));
+
+        device.set_connected(true);
+        assert!(matches!(sent(&mut sensor), Payload::Field(_)));
+    }
+
+    #[test]
+    fn commands_change_only_their_fields() {
+        let device = Device::new(ADDRESS);
+        let mut sensor = block_on(Sensor::new(
+            ADDRESS,
+            device.interrupt(),
+            device.bus(),
+            NoDelay,
+            || 0,
+        ));
+        let conf1 = device.register(0x00);
+        let conf3 = device.register(0x02);
+
+        block_on(sensor.handle_command(Command::SetGain(Gain::TWO))).unwrap();
+        assert_eq!(device.register(0x00), conf1 & !0x0070 | 0x0020);
+
+        let resolution = Res3D {
+            x: Resolution::BIT17,
+            y: Resolution::BIT18,
+            z: Resolution::BIT19,
+        };
+        block_on(sensor.handle_command(Command::SetResolution(resolution))).unwrap();
+        let conf3 = conf3 & !0x07E0 | 0b11_10_01 << 5;
+        assert_eq!(device.register(0x02), conf3);
+        assert_eq!(sensor.mlx.state.unwrap().resolution, resolution);
+
+        block_on(sensor.handle_command(Command::SetOversampling(Oversampling::TWO))).unwrap();
+        assert_eq!(device.register(0x02), conf3 & !0x0003 | 0x0002);
+    }
+
+    #[test]
+    fn sends_an_error_frame_for_an_undecodable_reading() {
+        let device = Device::new(ADDRESS);
+        let mut sensor = block_on(Sensor::new(
+            ADDRESS,
+            device.interrupt(),
+            device.bus(),
+            NoDelay,
+            || 0,
+        ));
+        // RES_X = 3 outputs counts offset by 2^14, so 0 is far beyond its range.
+        let data = (device.register(0x02) | 3 << 5).to_be_bytes();
+        block_on(sensor.handle_command(Command::WriteRegister {
+            address: 0x02,
+            data,
+        }))
+        .unwrap();
+        device.set_field(0, 0x8000, 0x8000);
+        assert!(matches!(
+            sent(&mut sensor),
+            Payload::Error(SensorError::Decode(DecodeError::OutOfRange {
+                axis: Axis::X,
+                resolution: Resolution::BIT19,
+                counts: -16384,
+            }))
+        ));
+
+        device.set_field(0x4000, 0x8000, 0x8000);
+        assert!(matches!(sent(&mut sensor), Payload::Field(_)));
+    }
+
+    #[test]
+    fn sends_an_error_frame_without_a_configuration() {
+        let device = Device::new(ADDRESS);
+        let mut sensor = block_on(Sensor::new(
+            ADDRESS,
+            device.interrupt(),
+            device.bus(),
+            NoDelay,
+            || 0,
+        ));
+        // HALLCONF 0x5 is not one the conversions support.
+        let data = (device.register(0x00) & !0x000F | 0x5).to_be_bytes();
+        block_on(sensor.handle_command(Command::WriteRegister {
+            address: 0x00,
+            data,
+        }))
+        .unwrap();
+        assert!(matches!(
+            sent(&mut sensor),
+            Payload::Error(SensorError::NotConfigured)
+        ));
+        sensor.set_format(StreamFormat::Raw);
+        assert!(matches!(
+            sent(&mut sensor),
+            Payload::Error(SensorError::NotConfigured)
+        ));
+    }
+
+    fn x_of(payload: Payload) -> f64 {
+        match payload {
+            Payload::Field(message) => message.field.x.unwrap().microtesla(),
+            payload => panic!("expected a field, got {:?}", payload),
+        }
+    }
+
+    #[test]
+    fn auto_zero_subtracts_the_captured_field() {
+        let device = Device::new(ADDRESS);
+        let mut sensor = block_on(Sensor::new(
+            ADDRESS,
+            device.interrupt(),
+            device.bus(),
+            NoDelay,
+            || 0,
+        ));
+        device.set_field(100, 0, 0);
+        let background = x_of(sent(&mut sensor));
+        assert!(background > 0.0);
+
+        let zeroed = block_on(sensor.handle_command(Command::AutoZero { readings: 4 }));
+        assert_eq!(zeroed, Ok(Ack::Done));
+        assert_eq!(x_of(sent(&mut sensor)), 0.0);
+        device.set_field(300, 0, 0);
+        assert!((x_of(sent(&mut sensor)) - 2.0 * background).abs() < 1e-9);
+
+        block_on(sensor.handle_command(Command::AutoZero { readings: 0 })).unwrap();
+        assert!((x_of(sent(&mut sensor)) - 3.0 * background).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reconfigures_after_a_reset() {
+        let device = Device::new(ADDRESS);
+        let mut sensor = block_on(Sensor::new(
+            ADDRESS,
+            device.interrupt(),
+            device.bus(),
+            NoDelay,
+            || 0,
+        ));
+        block_on(sensor.handle_command(Command::SetGain(Gain::TWO))).unwrap();
+        assert_eq!(sensor.mlx.state.unwrap().gain, Gain::TWO);
+
+        device.power_cycle();
+        assert!(matches!(
+            sent(&mut sensor),
+            Payload::Error(SensorError::Reset)
+        ));
+        assert!(matches!(sent(&mut sensor), Payload::Field(_)));
+        assert_eq!(sensor.mlx.state.unwrap().gain, Gain::SEVEN);
+    }
+}
@@ -0,0 +1,742 @@
+//! A software model of the MLX90393, to run the driver without a board.
+//!
+//! [`Device`] keeps the register file, the non-volatile memory, the measurement
+//! mode and the data of the last conversion. [`Bus`] answers the command bytes the
+//! way the sensor does on I2C, and [`Interrupt`] follows its INT line.
+//!
+//! Conversions complete as soon as they are started: a single measurement or a
+//! burst raises INT right away, and a burst raises it again after every read. In
+//! wake-on-change mode INT is raised whenever the simulated field or temperature
+//! changes.
+//!
+//! Faults are injected with [`Device::set_connected`], [`Device::power_cycle`],
+//! [`Device::inject_sed`] and [`Device::set_stuck_register`].
+use core::cell::RefCell;
+use core::convert::Infallible;
+use core::future::poll_fn;
+use core::task::{Poll, Waker};
+
+use data_transfer::memory::TempRef;
+use data_transfer::messaging::MeasurementMode;
+use embedded_hal::digital::ErrorType as PinErrorType;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::{
+    ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation, SevenBitAddress,
+};
+
+/// Number of 16 bit words in the register file, customer and Melexis areas included.
+pub const REGISTERS: usize = 0x40;
+
+/// Register 0: GAIN_SEL 7 and HALLCONF 0xC, as the sensors ship.
+const DEFAULT_CONF1: u16 = 0x007C;
+const TREF_REGISTER: usize = 0x24;
+
+const STATUS_BURST: u8 = 0b1000_0000;
+const STATUS_WOC: u8 = 0b0100_0000;
+const STATUS_SM: u8 = 0b0010_0000;
+const STATUS_ERROR: u8 = 0b0001_0000;
+const STATUS_SED: u8 = 0b0000_1000;
+const STATUS_RS: u8 = 0b0000_0100;
+
+struct State {
+    address: u8,
+    connected: bool,
+    /// Flags reported in the status of the next command, then cleared.
+    pending: u8,
+    /// Register that ignores writes.
+    stuck: Option<u8>,
+    registers: [u16; REGISTERS],
+    memory: [u16; REGISTERS],
+    mode: Option<MeasurementMode>,
+    /// `[T, X, Y, Z]`, in the order RM returns them.
+    data: [u16; 4],
+    response: [u8; 9],
+    response_len: usize,
+    interrupt: bool,
+    rising_edges: u32,
+    falling_edges: u32,
+    waker: Option<Waker>,
+}
+
+impl State {
+    fn set_interrupt(&mut self, level: bool) {
+        if self.interrupt == level {
+            return;
+        }
+        match level {
+            true => self.rising_edges += 1,
+            false => self.falling_edges += 1,
+        }
+        self.interrupt = level;
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn mode_bits(&self) -> u8 {
+        match self.mode {
+            None => 0,
+            Some(MeasurementMode::Burst) => STATUS_BURST,
+            Some(MeasurementMode::WakeOnChange) => STATUS_WOC,
+            Some(MeasurementMode::Single) => STATUS_SM,
+        }
+    }
+
+    fn respond(&mut self, flags: u8, data: &[u8]) {
+        // D1-D0: the response holds 2 + 2 * D data bytes.
+        let count = (data.len() / 2).saturating_sub(1) as u8;
+        self.response = [0; 9];
+        self.response[0] = self.mode_bits() | flags | core::mem::take(&mut self.pending) | count;
+        self.response[1..=data.len()].copy_from_slice(data);
+        self.response_len = data.len() + 1;
+    }
+
+    fn start(&mut self, mode: MeasurementMode) -> u8 {
+        if self.mode.is_some() {
+            return STATUS_ERROR;
+        }
+        self.mode = Some(mode);
+        if mode != MeasurementMode::WakeOnChange {
+            self.set_interrupt(true);
+        }
+        0
+    }
+
+    fn execute(&mut self, bytes: &[u8]) {
+        let Some(&command) = bytes.first() else {
+            return;
+        };
+        let axes = command & 0x0F;
+        match (command >> 4, bytes) {
+            (0x1, _) => {
+                let flags = self.start(MeasurementMode::Burst);
+                self.respond(flags, &[]);
+            }
+            (0x2, _) => {
+                let flags = self.start(MeasurementMode::WakeOnChange);
+                self.respond(flags, &[]);
+            }
+            (0x3, _) => {
+                let flags = self.start(MeasurementMode::Single);
+                self.respond(flags, &[]);
+            }
+            (0x4, _) => {
+                let mut data = [0; 8];
+                let mut len = 0;
+                for (bit, word) in self.data.into_iter().enumerate() {
+                    if axes & (1 << bit) != 0 {
+                        data[len..len + 2].copy_from_slice(&word.to_be_bytes());
+                        len += 2;
+                    }
+                }
+                self.respond(0, &data[..len]);
+                self.set_interrupt(false);
+                match self.mode {
+                    Some(MeasurementMode::Single) => self.mode = None,
+                    Some(MeasurementMode::Burst) => self.set_interrupt(true),
+                    _ => {}
+                }
+            }
+            (0x5, [_, location, ..]) => {
+                let word = self.registers[(*location >> 2) as usize % REGISTERS];
+                self.respond(0, &word.to_be_bytes());
+            }
+            (0x6, [_, high, low, location, ..]) => {
+                if self.stuck != Some(*location >> 2) {
+                    self.registers[(*location >> 2) as usize % REGISTERS] =
+                        u16::from_be_bytes([*high, *low]);
+                }
+                self.respond(0, &[]);
+            }
+            (0x8, _) => {
+                self.mode = None;
+                self.set_interrupt(false);
+                self.respond(0, &[]);
+            }
+            (0xD, _) => {
+                self.registers = self.memory;
+                self.respond(0, &[]);
+            }
+            (0xE, _) => {
+                self.memory = self.registers;
+                self.respond(0, &[]);
+            }
+            (0xF, _) => {
+                self.registers = self.memory;
+                self.mode = None;
+                self.set_interrupt(false);
+                self.respond(STATUS_RS, &[]);
+            }
+            _ => self.respond(STATUS_ERROR, &[]),
+        }
+    }
+
+    fn read(&self, buffer: &mut [u8]) {
+        buffer.fill(0);
+        let len = buffer.len().min(self.response_len);
+        buffer[..len].copy_from_slice(&self.response[..len]);
+    }
+
+    fn set_data(&mut self, data: [u16; 4]) {
+        if data != self.data && self.mode == Some(MeasurementMode::WakeOnChange) {
+            self.set_interrupt(true);
+        }
+        self.data = data;
+    }
+}
+
+/// A simulated MLX90393 at one I2C address.
+pub struct Device {
+    state: RefCell<State>,
+}
+
+impl Device {
+    pub fn new(address: u8) -> Self {
+        let mut memory = [0; REGISTERS];
+        memory[0] = DEFAULT_CONF1;
+        memory[TREF_REGISTER] = u16::from_be_bytes(TempRef::TYPICAL.offset);
+        Self {
+            state: RefCell::new(State {
+                address,
+                connected: true,
+                pending: 0,
+                stuck: None,
+                registers: memory,
+                memory,
+                mode: None,
+                data: [memory[TREF_REGISTER], 0, 0, 0],
+                response: [0; 9],
+                response_len: 0,
+                interrupt: false,
+                rising_edges: 0,
+                falling_edges: 0,
+                waker: None,
+            }),
+        }
+    }
+
+    /// The I2C side of the sensor, to hand to the driver.
+    pub fn bus(&self) -> Bus<'_> {
+        Bus { device: self }
+    }
+
+    /// The INT line of the sensor, to hand to the driver.
+    pub fn interrupt(&self) -> Interrupt<'_> {
+        Interrupt { device: self }
+    }
+
+    /// Sets the counts the next conversions return for each axis.
+    pub fn set_field(&self, x: u16, y: u16, z: u16) {
+        let mut state = self.state.borrow_mut();
+        let t = state.data[0];
+        state.set_data([t, x, y, z]);
+    }
+
+    /// Sets the counts the next conversions return for the temperature.
+    pub fn set_temperature(&self, t: u16) {
+        let mut state = self.state.borrow_mut();
+        let [_, x, y, z] = state.data;
+        state.set_data([t, x, y, z]);
+    }
+
+    pub fn register(&self, address: u8) -> u16 {
+        self.state.borrow().registers[address as usize]
+    }
+
+    pub fn set_register(&self, address: u8, value: u16) {
+        self.state.borrow_mut().registers[address as usize] = value;
+    }
+
+    /// The non-volatile copy of a register, loaded by HR and reset, saved by HS.
+    pub fn stored_register(&self, address: u8) -> u16 {
+        self.state.borrow().memory[address as usize]
+    }
+
+    /// The running measurement mode, if any.
+    pub fn mode(&self) -> Option<MeasurementMode> {
+        self.state.borrow().mode
+    }
+
+    /// A disconnected sensor does not acknowledge anything.
+    pub fn set_connected(&self, connected: bool) {
+        self.state.borrow_mut().connected = connected;
+    }
+
+    /// Resets the sensor behind the driver's back, as a brown-out would: the
+    /// registers are reloaded and the next status reports RS.
+    pub fn power_cycle(&self) {
+        let mut state = self.state.borrow_mut();
+        state.registers = state.memory;
+        state.mode = None;
+        state.set_interrupt(false);
+        state.pending |= STATUS_RS;
+    }
+
+    /// Reports SED in the status of the next command.
+    pub fn inject_sed(&self) {
+        self.state.borrow_mut().pending |= STATUS_SED;
+    }
+
+    /// Makes writes to the register at `address` succeed without changing it, as a
+    /// corrupted register would, or lets every register be written again with `None`.
+    pub fn set_stuck_register(&self, address: Option<u8>) {
+        self.state.borrow_mut().stuck = address;
+    }
+
+    /// Whether INT is high, meaning a conversion is waiting to be read.
+    pub fn interrupt_level(&self) -> bool {
+        self.state.borrow().interrupt
+    }
+}
+
+pub struct Bus<'a> {
+    device: &'a Device,
+}
+
+impl ErrorType for Bus<'_> {
+    type Error = ErrorKind;
+}
+
+impl I2c<SevenBitAddress> for Bus<'_> {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let mut state = self.device.state.borrow_mut();
+        if address != state.address || !state.connected {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => state.execute(bytes),
+                Operation::Read(buffer) => state.read(buffer),
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Interrupt<'a> {
+    device: &'a Device,
+}
+
+impl Interrupt<'_> {
+    async fn until(&self, ready: impl Fn(&State) -> bool) {
+        poll_fn(|cx| {
+            let mut state = self.device.state.borrow_mut();
+            match ready(&state) {
+                true => Poll::Ready(()),
+                false => {
+                    state.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl PinErrorType for Interrupt<'_> {
+    type Error = Infallible;
+}
+
+impl Wait for Interrupt<'_> {
+    async fn wait_for_high(&mut self) -> Result<(), Infallible> {
+        self.until(|state| state.interrupt).await;
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Infallible> {
+        self.until(|state| !state.interrupt).await;
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Infallible> {
+        let edges = self.device.state.borrow().rising_edges;
+        self.until(|state| state.rising_edges != edges).await;
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Infallible> {
+        let edges = self.device.state.borrow().falling_edges;
+        self.until(|state| state.falling_edges != edges).await;
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Infallible> {
+        let edges = {
+            let state = self.device.state.borrow();
+            state.rising_edges + state.falling_edges
+        };
+        self.until(|state| state.rising_edges + state.falling_edges != edges)
+            .await;
+        Ok(())
+    }
+}
+
+/// A delay that returns right away, since simulated conversions take no time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{Command, CommandData, RunCommand};
+    use crate::{Configuration, Error, MLX90393};
+    use data_transfer::conversions::{Axis, DecodeError};
+    use data_transfer::memory::{DigitalFilter, Gain, HallConf, Oversampling, Res3D, Resolution};
+    use futures::executor::block_on;
+
+    const ADDRESS: u8 = 0x0C;
+
+    type Driver<'a> = MLX90393<Bus<'a>, Interrupt<'a>, NoDelay>;
+
+    fn driver(device: &Device) -> Driver<'_> {
+        MLX90393::new(ADDRESS, device.interrupt(), device.bus(), NoDelay)
+    }
+
+    #[test]
+    fn reset_sets_rs_and_restores_the_registers() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        device.set_register(0x00, 0x0000);
+        block_on(async {
+            mlx.set_burst::<true, true, true, true>().await.unwrap();
+            assert_eq!(
+                mlx.run_command(Command::reset()).await.err(),
+                Some(Error::Reset)
+            );
+            let (status, _) = mlx.run_command(Command::exit()).await.unwrap();
+            assert!(!status.rs);
+            assert!(!status.burst_mode);
+            mlx.reset().await.unwrap();
+        });
+        assert_eq!(device.register(0x00), DEFAULT_CONF1);
+        assert_eq!(device.mode(), None);
+    }
+
+    #[test]
+    fn reads_and_writes_registers() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        block_on(async {
+            mlx.write_register_at(0x07, [0x12, 0x34]).await.unwrap();
+            assert_eq!(mlx.read_register_at(0x07).await.unwrap(), [0x12, 0x34]);
+            assert_eq!(
+                mlx.read_register::<0x00>().await.unwrap().gain(),
+                Gain::SEVEN
+            );
+        });
+        assert_eq!(device.register(0x07), 0x1234);
+    }
+
+    #[test]
+    fn memory_store_and_recall() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        block_on(async {
+            mlx.write_register_at(0x08, [0xAB, 0xCD]).await.unwrap();
+            mlx.memory_store().await.unwrap();
+            mlx.write_register_at(0x08, [0x00, 0x00]).await.unwrap();
+            mlx.memory_recall().await.unwrap();
+        });
+        assert_eq!(device.stored_register(0x08), 0xABCD);
+        assert_eq!(device.register(0x08), 0xABCD);
+    }
+
+    fn configuration() -> Configuration {
+        Configuration {
+            gain: Gain::THREE,
+            hall_configuration: HallConf::TWOPHASE,
+            resolution: Res3D {
+                x: Resolution::BIT17,
+                y: Resolution::BIT17,
+                z: Resolution::BIT18,
+            },
+            oversampling: Oversampling::TWO,
+            temperature_oversampling: Oversampling::ONE,
+            digital_filter: DigitalFilter::FIVE,
+            offsets: [0x8001, 0x8002, 0x8003],
+            wo_xy_threshold: 0x0100,
+            wo_z_threshold: 0x0200,
+        }
+    }
+
+    #[test]
+    fn configure_writes_the_registers() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        // Z-series and BIST are not part of the configuration.
+        device.set_register(0x00, DEFAULT_CONF1 | 0x0180);
+        block_on(async {
+            mlx.set_burst::<true, true, true, true>().await.unwrap();
+            mlx.configure(&configuration(), false).await.unwrap();
+        });
+        assert_eq!(device.mode(), None);
+        assert_eq!(device.register(0x00), 0x01B0);
+        assert_eq!(device.register(0x02), 0x0CB6);
+        assert_eq!(device.register(0x06), 0x8003);
+        assert_eq!(device.register(0x08), 0x0200);
+        assert_eq!(device.stored_register(0x00), DEFAULT_CONF1);
+        let state = mlx.state.unwrap();
+        assert_eq!(state.gain, Gain::THREE);
+        assert_eq!(state.resolution.z, Resolution::BIT18);
+    }
+
+    #[test]
+    fn configure_can_persist_across_a_power_cycle() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        block_on(mlx.configure(&configuration(), true)).unwrap();
+        device.power_cycle();
+        for register in 0x04..=0x08 {
+            assert_eq!(device.stored_register(register), device.register(register));
+        }
+        assert_eq!(device.register(0x04), 0x8001);
+        assert_eq!(device.register(0x07), 0x0100);
+    }
+
+    #[test]
+    fn configure_reports_a_register_that_does_not_take_the_write() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        device.set_stuck_register(Some(0x05));
+        assert_eq!(
+            block_on(mlx.configure(&configuration(), true)).err(),
+            Some(Error::Verify { register: 0x05 })
+        );
+        assert_eq!(device.stored_register(0x04), 0);
+    }
+
+    #[test]
+    fn single_measurement_returns_the_field() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        device.set_field(100, (-200i16) as u16, 300);
+        block_on(async {
+            mlx.set_measurement_configuration().await.unwrap();
+            mlx.set_single_measurmenet::<true, true, true, true>()
+                .await
+                .unwrap();
+            assert_eq!(device.mode(), Some(MeasurementMode::Single));
+            let (status, field) = mlx.get_field::<true, true, true, true>().await.unwrap();
+            assert!(status.sm_mode);
+            assert_eq!(status.data, 3);
+            assert!(field.x.unwrap().value() > 0.0);
+            assert!(field.y.unwrap().value() < 0.0);
+            assert!((field.t.unwrap().celsius() - 35.0).abs() < 1e-9);
+        });
+        assert_eq!(device.mode(), None);
+        assert!(!device.interrupt_level());
+    }
+
+    #[test]
+    fn out_of_range_reading_is_a_decode_error() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        // RES_X = 2 outputs counts offset by 2^15, so 0 is far beyond its range.
+        device.set_register(0x02, device.register(0x02) & !0x0060 | 2 << 5);
+        device.set_field(0, 0, 0);
+        let result = block_on(async {
+            mlx.set_measurement_configuration().await.unwrap();
+            mlx.set_single_measurmenet::<true, true, true, true>()
+                .await
+                .unwrap();
+            mlx.get_field::<true, true, true, true>().await.map(|_| ())
+        });
+        assert_eq!(
+            result,
+            Err(Error::Decode(DecodeError::OutOfRange {
+                axis: Axis::X,
+                resolution: Resolution::BIT18,
+                counts: -32768,
+            }))
+        );
+    }
+
+    /// Measures the values selected by the parameters and checks each one lands
+    /// in its own field.
+    fn read_measurement<const X: bool, const Y: bool, const Z: bool, const TEMP: bool>() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        device.set_field(0x1112, 0x2223, 0x3334);
+        device.set_temperature(0x4445);
+        let (_status, bits) = block_on(async {
+            mlx.set_single_measurmenet::<X, Y, Z, TEMP>().await?;
+            mlx.get_measurement::<X, Y, Z, TEMP>().await
+        })
+        .unwrap_or_else(|err| panic!("{:?} for {:?}", err, (X, Y, Z, TEMP)));
+        let expected = |selected: bool, value: u16| selected.then_some(value.to_be_bytes());
+        assert_eq!(
+            (bits.x, bits.y, bits.z, bits.temp),
+            (
+                expected(X, 0x1112),
+                expected(Y, 0x2223),
+                expected(Z, 0x3334),
+                expected(TEMP, 0x4445)
+            ),
+            "{:?}",
+            (X, Y, Z, TEMP)
+        );
+        assert_eq!(device.mode(), None);
+    }
+
+    #[test]
+    fn reads_every_combination_of_axes() {
+        read_measurement::<false, false, false, false>();
+        read_measurement::<false, false, false, true>();
+        read_measurement::<false, false, true, false>();
+        read_measurement::<false, false, true, true>();
+        read_measurement::<false, true, false, false>();
+        read_measurement::<false, true, false, true>();
+        read_measurement::<false, true, true, false>();
+        read_measurement::<false, true, true, true>();
+        read_measurement::<true, false, false, false>();
+        read_measurement::<true, false, false, true>();
+        read_measurement::<true, false, true, false>();
+        read_measurement::<true, false, true, true>();
+        read_measurement::<true, true, false, false>();
+        read_measurement::<true, true, false, true>();
+        read_measurement::<true, true, true, false>();
+        read_measurement::<true, true, true, true>();
+    }
+
+    #[test]
+    fn burst_keeps_converting_until_exit() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        block_on(async {
+            mlx.set_burst::<true, true, true, true>().await.unwrap();
+            for z in 1..4 {
+                device.set_field(0, 0, z);
+                let (status, bits) = mlx
+                    .get_measurement::<false, false, true, false>()
+                    .await
+                    .unwrap();
+                assert!(status.burst_mode);
+                assert_eq!(bits.z, Some(z.to_be_bytes()));
+            }
+            mlx.exit().await.unwrap();
+        });
+        assert_eq!(device.mode(), None);
+    }
+
+    #[test]
+    fn wake_on_change_waits_for_the_field_to_change() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        block_on(async {
+            mlx.set_woc::<true, true, true, false>().await.unwrap();
+            assert!(!device.interrupt_level());
+            let (measured, ()) =
+                futures::join!(mlx.get_measurement::<true, false, false, false>(), async {
+                    device.set_field(1, 2, 3)
+                },);
+            let (status, bits) = measured.unwrap();
+            assert!(status.woc_mode);
+            assert_eq!(bits.x, Some(1u16.to_be_bytes()));
+        });
+        assert!(!device.interrupt_level());
+    }
+
+    #[test]
+    fn starting_a_mode_twice_is_an_error() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        block_on(async {
+            mlx.set_burst::<true, true, true, true>().await.unwrap();
+            assert_eq!(
+                mlx.set_single_measurmenet::<true, true, true, true>().await,
+                Err(Error::Device)
+            );
+        });
+        assert_eq!(device.mode(), Some(MeasurementMode::Burst));
+    }
+
+    #[test]
+    fn reads_the_measurement_configuration() {
+        let device = Device::new(ADDRESS);
+        device.set_register(0x02, 0b10 << 5 | 0b01 << 7 | 0b11 << 9);
+        let mut mlx = driver(&device);
+        let config = block_on(mlx.get_measurement_configuration())
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.gain, Gain::SEVEN);
+        assert_eq!(config.resolution.x, Resolution::BIT18);
+        assert_eq!(config.resolution.y, Resolution::BIT17);
+        assert_eq!(config.resolution.z, Resolution::BIT19);
+        assert_eq!(config.temp_ref, TempRef::TYPICAL);
+    }
+
+    #[test]
+    fn missing_sensor_is_a_bus_error() {
+        let device = Device::new(ADDRESS);
+        let mut other = MLX90393::new(ADDRESS + 1, device.interrupt(), device.bus(), NoDelay);
+        let nack = Error::Bus(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        assert_eq!(block_on(other.read_register_at(0x00)), Err(nack));
+
+        let mut mlx = driver(&device);
+        device.set_connected(false);
+        assert_eq!(block_on(mlx.exit()), Err(nack));
+        device.set_connected(true);
+        assert_eq!(block_on(mlx.exit()), Ok(()));
+    }
+
+    #[test]
+    fn reports_status_flags() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        block_on(async {
+            device.inject_sed();
+            assert_eq!(
+                mlx.read_register_at(0x00).await,
+                Err(Error::SingleErrorDetection)
+            );
+            mlx.set_burst::<true, true, true, true>().await.unwrap();
+            device.power_cycle();
+            assert_eq!(
+                mlx.get_measurement::<true, true, true, true>().await.err(),
+                Some(Error::Timeout)
+            );
+            assert_eq!(mlx.exit().await, Err(Error::Reset));
+            assert_eq!(mlx.exit().await, Ok(()));
+        });
+    }
+
+    #[test]
+    fn reports_a_wrong_data_length() {
+        /// Reads two values but asks for all four.
+        struct Short;
+        impl RunCommand<Short, 1, 5> for CommandData<Short> {
+            fn write_command(&self) -> [u8; 1] {
+                [0x4F]
+            }
+        }
+
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        assert_eq!(
+            block_on(mlx.run_command(CommandData { command: Short })).err(),
+            Some(Error::DataLength {
+                expected: 4,
+                received: 8
+            })
+        );
+    }
+
+    #[test]
+    fn times_out_without_a_conversion() {
+        let device = Device::new(ADDRESS);
+        let mut mlx = driver(&device);
+        assert_eq!(
+            block_on(mlx.get_measurement::<true, true, true, true>()).err(),
+            Some(Error::Timeout)
+        );
+    }
+}
@@ -1,16 +1,3 @@
-use embedded_hal_async::i2c::I2c;
-
-use crate::mlx90393::commands::CommandData;
-
-//enum Event {
-//Command(CommandData),
-//MeasurementReady,
-//}
-
-trait IsSensorMode {
-    fn new() -> Self;
-}
-
 pub struct NoMode;
 pub struct Burst;
 pub struct WakeOnChange;
@@ -41,6 +28,8 @@ impl IsMode for SingleMeasurement {
 }
 
 pub struct SensorState<S, T> {
+    /// Only the type matters.
+    #[allow(dead_code)]
     pub(crate) state: S,
     pub(crate) mode: T,
 }
@@ -50,7 +39,7 @@ pub struct Measuring;
 pub struct Measured;
 
 impl<T: IsMode> From<SensorState<Idle, NoMode>> for SensorState<Measuring, T> {
-    fn from(value: SensorState<Idle, NoMode>) -> Self {
+    fn from(_value: SensorState<Idle, NoMode>) -> Self {
         Self {
             state: Measuring,
             mode: T::new(),
@@ -68,7 +57,7 @@ impl<T> From<SensorState<Measuring, T>> for SensorState<Measured, T> {
 }
 
 impl<T> From<SensorState<Measured, T>> for SensorState<Idle, NoMode> {
-    fn from(value: SensorState<Measured, T>) -> Self {
+    fn from(_value: SensorState<Measured, T>) -> Self {
         Self {
             state: Idle,
             mode: NoMode,
@@ -77,7 +66,7 @@ impl<T> From<SensorState<Measured, T>> for SensorState<Idle, NoMode> {
 }
 
 impl From<SensorState<Measured, Burst>> for SensorState<Measuring, Burst> {
-    fn from(value: SensorState<Measured, Burst>) -> Self {
+    fn from(_value: SensorState<Measured, Burst>) -> Self {
         Self {
             state: Measuring,
             mode: Burst,
@@ -86,7 +75,7 @@ impl From<SensorState<Measured, Burst>> for SensorState<Measuring, Burst> {
 }
 
 impl From<SensorState<Measured, WakeOnChange>> for SensorState<Measuring, WakeOnChange> {
-    fn from(value: SensorState<Measured, WakeOnChange>) -> Self {
+    fn from(_value: SensorState<Measured, WakeOnChange>) -> Self {
         Self {
             state: Measuring,
             mode: WakeOnChange,
@@ -95,7 +84,7 @@ impl From<SensorState<Measured, WakeOnChange>> for SensorState<Measuring, WakeOn
 }
 
 impl<T> From<SensorState<Measured, T>> for SensorState<Measuring, SingleMeasurement> {
-    fn from(value: SensorState<Measured, T>) -> Self {
+    fn from(_value: SensorState<Measured, T>) -> Self {
         Self {
             state: Measuring,
             mode: SingleMeasurement,